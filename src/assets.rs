@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 use bevy_asset_loader::AssetCollection;
 
+use crate::enemy_definitions::EnemyDefinitions;
+use crate::waves::WaveTimeline;
+
 #[derive(AssetCollection)]
 pub struct IconsetAssets {
     #[asset(texture_atlas(tile_size_x = 32., tile_size_y = 32., columns = 18, rows = 50))]
@@ -15,3 +18,18 @@ pub struct IconsetAssets {
     #[asset(path = "images/iconset_halloween_standalone.png")]
     pub iconset_halloween_standalone: Handle<TextureAtlas>,
 }
+
+#[derive(AssetCollection)]
+pub struct FontAssets {
+    #[asset(path = "fonts/DejaVuSans-Bold.ttf")]
+    pub dejavu_sans_bold: Handle<Font>,
+}
+
+#[derive(AssetCollection)]
+pub struct DataAssets {
+    #[asset(path = "data/main.enemies.ron")]
+    pub enemies: Handle<EnemyDefinitions>,
+
+    #[asset(path = "data/main.waves.ron")]
+    pub waves: Handle<WaveTimeline>,
+}
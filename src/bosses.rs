@@ -0,0 +1,178 @@
+use std::f32::consts::PI;
+
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::enemies::*;
+use crate::waves::*;
+use crate::{Health, Player};
+
+const BOSS_CHARGE_COOLDOWN: f32 = 3.; // seconds
+const BOSS_CHARGE_SPEED: f32 = 12.;
+const BOSS_SUMMON_COOLDOWN: f32 = 6.; // seconds
+const BOSS_BURST_COOLDOWN: f32 = 2.5; // seconds
+const BOSS_BURST_PROJECTILES: usize = 16;
+const BOSS_PROJECTILE_DAMAGE: usize = 10;
+
+const BOSS_HEALTH_BAR_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BOSS_HEALTH_COLOR: Color = Color::rgb(0.8, 0.1, 0.1);
+
+/// The attack of a boss, it changes as the boss loses health.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BossPhase {
+    /// Dashes towards the player.
+    Charge,
+    /// Spawns a group of minions around itself.
+    Summon,
+    /// Fires projectiles in every direction.
+    Burst,
+}
+
+impl BossPhase {
+    fn from_health(health: usize, max_health: usize) -> BossPhase {
+        let ratio = health as f32 / max_health.max(1) as f32;
+        if ratio > 2. / 3. {
+            BossPhase::Charge
+        } else if ratio > 1. / 3. {
+            BossPhase::Summon
+        } else {
+            BossPhase::Burst
+        }
+    }
+
+    fn cooldown(&self) -> f32 {
+        match self {
+            BossPhase::Charge => BOSS_CHARGE_COOLDOWN,
+            BossPhase::Summon => BOSS_SUMMON_COOLDOWN,
+            BossPhase::Burst => BOSS_BURST_COOLDOWN,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Boss {
+    pub max_health: usize,
+    pub definition: BossDefinition,
+    phase: BossPhase,
+    attack: Timer,
+}
+
+impl Boss {
+    pub fn new(definition: BossDefinition, max_health: usize) -> Boss {
+        let phase = BossPhase::Charge;
+        Boss { max_health, definition, phase, attack: Timer::from_seconds(phase.cooldown(), true) }
+    }
+}
+
+#[derive(Component)]
+pub struct BossHealthBar;
+
+#[derive(Component)]
+pub struct BossHealthFill;
+
+/// Switches the phase of the bosses according to their health and attacks when it's time to.
+pub fn boss_attacks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut summons: EventWriter<SummonWave>,
+    player_query: Query<&Transform, With<Player>>,
+    mut boss_query: Query<(&mut Boss, &Health, &Transform, &mut Velocity), Without<Player>>,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    for (mut boss, health, transform, mut velocity) in boss_query.iter_mut() {
+        let phase = BossPhase::from_health(health.0, boss.max_health);
+        if phase != boss.phase {
+            boss.phase = phase;
+            boss.attack = Timer::from_seconds(phase.cooldown(), true);
+        }
+
+        if !boss.attack.tick(time.delta()).just_finished() {
+            continue;
+        }
+
+        let position = transform.translation.xy();
+        match boss.phase {
+            BossPhase::Charge => {
+                let direction = (player_transform.translation.xy() - position).normalize_or_zero();
+                velocity.linear = (direction * BOSS_CHARGE_SPEED).extend(0.);
+            }
+            BossPhase::Summon => {
+                summons.send(SummonWave { wave: boss.definition.minion_wave(), position })
+            }
+            BossPhase::Burst => {
+                let step = 2. * PI / BOSS_BURST_PROJECTILES as f32;
+                for i in 0..BOSS_BURST_PROJECTILES {
+                    let angle = step * i as f32;
+                    let direction = Vec2::new(angle.cos(), angle.sin());
+                    spawn_enemy_projectile(
+                        &mut commands,
+                        position,
+                        direction,
+                        BOSS_PROJECTILE_DAMAGE,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Shows the total health of the bosses alive at the top of the screen.
+pub fn sync_boss_health_bar(
+    mut commands: Commands,
+    boss_query: Query<(&Boss, &Health), With<Enemy>>,
+    bar_query: Query<Entity, With<BossHealthBar>>,
+    mut fill_query: Query<&mut Style, With<BossHealthFill>>,
+) {
+    let (health, max_health) = boss_query
+        .iter()
+        .fold((0, 0), |(health, max), (boss, h)| (health + h.0, max + boss.max_health));
+
+    if max_health == 0 {
+        for bar in bar_query.iter() {
+            commands.entity(bar).despawn_recursive();
+        }
+        return;
+    }
+
+    let percent = 100. * health as f32 / max_health as f32;
+    if bar_query.iter().next().is_none() {
+        spawn_boss_health_bar(&mut commands, percent);
+    }
+
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(percent);
+    }
+}
+
+fn spawn_boss_health_bar(commands: &mut Commands, percent: f32) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect { left: Val::Percent(20.), top: Val::Px(20.), ..Default::default() },
+                size: Size::new(Val::Percent(60.), Val::Px(24.)),
+                padding: Rect::all(Val::Px(3.)),
+                ..Default::default()
+            },
+            color: BOSS_HEALTH_BAR_COLOR.into(),
+            ..Default::default()
+        })
+        .insert(BossHealthBar)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(percent), Val::Percent(100.)),
+                        ..Default::default()
+                    },
+                    color: BOSS_HEALTH_COLOR.into(),
+                    ..Default::default()
+                })
+                .insert(BossHealthFill);
+        });
+}
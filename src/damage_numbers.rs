@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::assets::*;
+use crate::weapons::EnemyDamaged;
+use crate::CAMERA_SCALE;
+
+const DAMAGE_NUMBER_LIFETIME: f32 = 0.6; // seconds
+const DAMAGE_NUMBER_RISE_SPEED: f32 = 1.5;
+const DAMAGE_NUMBER_Z: f32 = 150.;
+const MAX_DAMAGE_NUMBERS: usize = 512;
+
+const DAMAGE_NUMBER_FONT_SIZE: f32 = 24.;
+const DAMAGE_NUMBER_COLOR: Color = Color::WHITE;
+const CRIT_DAMAGE_NUMBER_FONT_SIZE: f32 = 34.;
+const CRIT_DAMAGE_NUMBER_COLOR: Color = Color::rgb(1., 0.75, 0.);
+
+/// The damage numbers that finished their animation, they are hidden
+/// and reused instead of spawning new text entities.
+#[derive(Default)]
+pub struct DamageNumberPool {
+    free: Vec<Entity>,
+    spawned: usize,
+}
+
+#[derive(Component)]
+pub struct DamageNumber(Timer);
+
+pub fn spawn_damage_numbers(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut pool: ResMut<DamageNumberPool>,
+    mut damages: EventReader<EnemyDamaged>,
+    mut number_query: Query<(&mut Text, &mut Transform, &mut Visibility, &mut DamageNumber)>,
+) {
+    for damage in damages.iter() {
+        let transform = Transform::from_translation(damage.position.extend(DAMAGE_NUMBER_Z))
+            .with_scale(Vec3::splat(CAMERA_SCALE));
+        let (font_size, color) = if damage.crit {
+            (CRIT_DAMAGE_NUMBER_FONT_SIZE, CRIT_DAMAGE_NUMBER_COLOR)
+        } else {
+            (DAMAGE_NUMBER_FONT_SIZE, DAMAGE_NUMBER_COLOR)
+        };
+
+        match pool.free.pop() {
+            Some(entity) => {
+                if let Ok((mut text, mut old_transform, mut visibility, mut number)) =
+                    number_query.get_mut(entity)
+                {
+                    let section = &mut text.sections[0];
+                    section.value.clear();
+                    let _ = write!(section.value, "{}", damage.damage);
+                    section.style.font_size = font_size;
+                    section.style.color = color;
+                    *old_transform = transform;
+                    visibility.is_visible = true;
+                    number.0.reset();
+                }
+            }
+            None if pool.spawned < MAX_DAMAGE_NUMBERS => {
+                pool.spawned += 1;
+                commands
+                    .spawn_bundle(Text2dBundle {
+                        text: Text::with_section(
+                            damage.damage.to_string(),
+                            TextStyle {
+                                font: font_assets.dejavu_sans_bold.clone(),
+                                font_size,
+                                color,
+                            },
+                            TextAlignment {
+                                vertical: VerticalAlign::Center,
+                                horizontal: HorizontalAlign::Center,
+                            },
+                        ),
+                        transform,
+                        ..Default::default()
+                    })
+                    .insert(DamageNumber(Timer::from_seconds(DAMAGE_NUMBER_LIFETIME, false)));
+            }
+            // There are already too many damage numbers on screen.
+            None => (),
+        }
+    }
+}
+
+/// Makes the damage numbers rise and fade out, then puts them back in the pool.
+pub fn animate_damage_numbers(
+    time: Res<Time>,
+    mut pool: ResMut<DamageNumberPool>,
+    mut number_query: Query<(
+        Entity,
+        &mut Text,
+        &mut Transform,
+        &mut Visibility,
+        &mut DamageNumber,
+    )>,
+) {
+    for (entity, mut text, mut transform, mut visibility, mut number) in number_query.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+
+        if number.0.tick(time.delta()).finished() {
+            visibility.is_visible = false;
+            pool.free.push(entity);
+            continue;
+        }
+
+        transform.translation.y += DAMAGE_NUMBER_RISE_SPEED * time.delta_seconds();
+        let alpha = 1. - number.0.percent();
+        for section in text.sections.iter_mut() {
+            section.style.color.set_a(alpha);
+        }
+    }
+}
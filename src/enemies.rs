@@ -2,13 +2,22 @@ use std::f32::consts::PI;
 
 use bevy::math::*;
 use bevy::prelude::*;
+use bevy::utils::HashMap;
 use heron::prelude::*;
 use rand::Rng;
+use serde::Deserialize;
 
 use crate::assets::*;
+use crate::bosses::Boss;
+use crate::enemy_definitions::*;
+use crate::flow_field::FlowField;
 use crate::game_sprites::*;
 use crate::helper::*;
-use crate::{GameLayer, Player, Velocity};
+use crate::spatial::EnemyGrid;
+use crate::status_effects::StatusEffects;
+use crate::waves::*;
+use crate::weapons::Projectile;
+use crate::{is_enemy_projectile_layer, is_player_layer, GameLayer, Health, Player, Velocity};
 
 const TRACKING_SPEED: f32 = 0.03;
 const TRACKING_MAX_SPEED: f32 = 1.0;
@@ -18,38 +27,148 @@ const SLOW_WALKING_MAX_SPEED: f32 = 1.0;
 
 const RUNNING_SPEED: f32 = 0.05;
 
+const ENCIRCLE_SPEED: f32 = 0.05;
+const ENCIRCLE_MAX_SPEED: f32 = 1.0;
+const ENCIRCLE_ORBIT_SPEED: f32 = 0.3; // radian per second
+
+const FLOCKING_SPEED: f32 = 0.03;
+const FLOCKING_MAX_SPEED: f32 = 1.0;
+const FLOCKING_NEIGHBOUR_RADIUS: f32 = 2.5;
+const FLOCKING_SEPARATION_RADIUS: f32 = 1.0;
+
+const CHARGING_SPEED: f32 = 0.03;
+const CHARGING_MAX_SPEED: f32 = 1.0;
+const CHARGING_TRIGGER_DISTANCE: f32 = 5.;
+const CHARGING_TELEGRAPH_DURATION: f32 = 0.8; // seconds
+const CHARGING_TELEGRAPH_FLASHES: f32 = 4.;
+const CHARGING_TELEGRAPH_COLOR: Color = Color::rgb(1.0, 0.3, 0.3);
+const CHARGING_DASH_SPEED: f32 = 14.;
+const CHARGING_DASH_DURATION: f32 = 0.5; // seconds
+const CHARGING_RECOVERY_DURATION: f32 = 1.2; // seconds
+const CHARGING_RECOVERY_BRAKE: f32 = 6.; // share of the speed lost per second
+
+const RANGED_SPEED: f32 = 0.03;
+const RANGED_MAX_SPEED: f32 = 1.0;
+const RANGED_PREFERRED_DISTANCE: f32 = 6.;
+const RANGED_FIRE_RANGE: f32 = 9.;
+const RANGED_FIRE_COOLDOWN: f32 = 2.5; // seconds
+
+const ENEMY_PROJECTILE_COLOR: Color = Color::rgb(0.9, 0.2, 0.1);
+const ENEMY_PROJECTILE_SPEED: f32 = 6.;
+const ENEMY_PROJECTILE_RADIUS: f32 = 0.25;
+const ENEMY_PROJECTILE_LIFETIME: f32 = 4.; // seconds
+
+const SPAWN_CLEARANCE: f32 = 0.8;
+const SPAWN_ATTEMPTS: usize = 4;
+
+const ELITE_HEALTH_MULTIPLIER: f32 = 3.;
+const ELITE_SCALE_MULTIPLIER: f32 = 1.5;
+const ELITE_XP_MULTIPLIER: usize = 3;
+
 #[derive(Component)]
 pub struct Enemy;
 
+/// The name of an enemy archetype in the `EnemyDefinitions`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Component)]
+#[serde(transparent)]
+pub struct EnemyKind(pub String);
+
+/// The stats of an enemy, copied from its definition when it spawns.
 #[derive(Component)]
-pub enum EnemyKind {
-    BlueFish,
-    BigRedFish,
-    Pumpkin,
-    SkeletonHead,
-    Knife,
+pub struct EnemyStats {
+    pub contact_damage: usize,
+    pub speed: f32,
+    pub knockback_resistance: f32,
+    pub xp: usize,
 }
 
-#[derive(Component)]
-pub struct EnemyWaveSize(pub usize);
+impl From<&EnemyDefinition> for EnemyStats {
+    fn from(definition: &EnemyDefinition) -> Self {
+        EnemyStats {
+            contact_damage: definition.damage,
+            speed: definition.speed,
+            knockback_resistance: definition.knockback_resistance,
+            xp: definition.xp,
+        }
+    }
+}
 
-#[derive(Component)]
-pub struct EnemyWavesCount(pub usize);
+/// What makes an enemy differ from its definition, kept to be applied again
+/// when the definitions are reloaded.
+#[derive(Component, Clone, Copy, Default)]
+pub struct EnemyModifiers {
+    pub difficulty: Difficulty,
+    /// Elites have more health, are bigger and give more xp.
+    pub elite: bool,
+}
 
-#[derive(Bundle)]
-pub struct EnemyWaveBundle {
+impl EnemyModifiers {
+    pub fn stats(&self, definition: &EnemyDefinition) -> EnemyStats {
+        let mut stats = EnemyStats::from(definition);
+        stats.speed *= self.difficulty.speed;
+        if self.elite {
+            stats.xp *= ELITE_XP_MULTIPLIER;
+        }
+        stats
+    }
+
+    pub fn health(&self, definition: &EnemyDefinition) -> usize {
+        let mut health = definition.health as f32 * self.difficulty.health;
+        if self.elite {
+            health *= ELITE_HEALTH_MULTIPLIER;
+        }
+        (health.round() as usize).max(1)
+    }
+
+    fn size_multiplier(&self) -> f32 {
+        if self.elite {
+            ELITE_SCALE_MULTIPLIER
+        } else {
+            1.
+        }
+    }
+
+    pub fn scale(&self, definition: &EnemyDefinition) -> f32 {
+        definition.scale * self.size_multiplier()
+    }
+
+    pub fn collision_shape(&self, definition: &EnemyDefinition) -> CollisionShape {
+        let (half_width, half_height) = definition.hitbox;
+        CollisionShape::Cuboid {
+            half_extends: Vec3::new(half_width, half_height, 0.) * self.size_multiplier(),
+            border_radius: None,
+        }
+    }
+}
+
+/// Sent every time an enemy dies.
+pub struct EnemyKilled {
     pub kind: EnemyKind,
-    pub timer: Timer,
-    pub size: EnemyWaveSize,
-    pub count: EnemyWavesCount,
-    pub movement_kind: MovementKind,
+    pub position: Vec2,
+    pub xp: usize,
+    pub boss: bool,
 }
 
-#[derive(Component)]
+#[derive(Debug, Clone, Copy, Deserialize)]
 pub enum MovementKind {
     Tracking,
     SlowWalking,
     RunningGroup,
+    Ranged,
+    /// The enemies of a group form a ring of `radius` around the player,
+    /// shrinking by `shrink` per second.
+    Encircle {
+        radius: f32,
+        shrink: f32,
+    },
+    Charging,
+    /// The enemies swim towards the player as a school, steered by their neighbours:
+    /// away from the closest ones, along their heading and towards their center.
+    Flocking {
+        separation: f32,
+        alignment: f32,
+        cohesion: f32,
+    },
 }
 
 /// An enemy that follows the position of the player.
@@ -67,6 +186,73 @@ pub struct RunningGroupMovement {
     pub direction: Vec2,
 }
 
+/// An enemy holding its place in a ring around the player, which tightens over time.
+#[derive(Component)]
+pub struct EncircleMovement {
+    /// The position of the enemy on the ring, in radian.
+    pub angle: f32,
+    pub radius: f32,
+    pub shrink: f32,
+}
+
+/// An enemy moving as part of a school, the fields are the weights of the steering rules.
+#[derive(Component)]
+pub struct FlockingMovement {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+}
+
+/// The steps a charging enemy goes through, in order, before approaching again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargePhase {
+    /// Walks towards the player until it's close enough.
+    Approaching,
+    /// Stands still and flashes, the direction of the dash is locked.
+    Telegraphing,
+    /// Rushes along the locked direction.
+    Dashing,
+    /// Slows down before approaching again.
+    Recovering,
+}
+
+/// An enemy that dashes at the player after a warning.
+#[derive(Component)]
+pub struct ChargingMovement {
+    pub phase: ChargePhase,
+    pub timer: Timer,
+    pub direction: Vec2,
+}
+
+impl Default for ChargingMovement {
+    fn default() -> Self {
+        ChargingMovement {
+            phase: ChargePhase::Approaching,
+            timer: Timer::from_seconds(0., false),
+            direction: Vec2::ZERO,
+        }
+    }
+}
+
+impl ChargingMovement {
+    fn enter(&mut self, phase: ChargePhase, duration: f32) {
+        self.phase = phase;
+        self.timer = Timer::from_seconds(duration, false);
+    }
+}
+
+/// An enemy that keeps its distance from the player and fires projectiles at it.
+#[derive(Component)]
+pub struct RangedMovement {
+    pub fire: Timer,
+}
+
+/// A projectile fired by an enemy, it goes through the other enemies and hurts the player.
+#[derive(Component)]
+pub struct EnemyProjectile {
+    pub damage: usize,
+}
+
 #[derive(Bundle)]
 pub struct EnemyBundle {
     #[bundle]
@@ -77,180 +263,70 @@ pub struct EnemyBundle {
     pub physic_material: PhysicMaterial,
     pub collision_shape: CollisionShape,
     pub collision_layers: CollisionLayers,
+    pub kind: EnemyKind,
+    pub stats: EnemyStats,
+    pub modifiers: EnemyModifiers,
+    pub health: Health,
+    pub status_effects: StatusEffects,
     pub _marker: Enemy,
 }
 
 impl EnemyBundle {
-    fn blue_fish(iconset_assets: &IconsetAssets, pos: Vec3) -> EnemyBundle {
-        let game_sprite = GameSpriteBundle {
-            sprite: SpriteSheetBundle {
-                transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.02)),
-                sprite: TextureAtlasSprite::new(162),
-                texture_atlas: iconset_assets.iconset_fantasy_standalone.clone(),
-                ..Default::default()
-            },
-            base_rotation: BaseSpriteRotation(23.0 * PI / 14.0),
-            ..Default::default()
-        };
-
-        EnemyBundle {
-            game_sprite,
-            velocity: Velocity::default(),
-            rigid_body: RigidBody::Dynamic,
-            damping: Damping::from_linear(1.),
-            physic_material: PhysicMaterial { density: 10.0, ..Default::default() },
-            collision_shape: CollisionShape::Cuboid {
-                half_extends: Vec3::new(0.3, 0.3, 0.),
-                border_radius: None,
-            },
-            collision_layers: CollisionLayers::none().with_group(GameLayer::Enemies).with_masks(&[
-                GameLayer::Enemies,
-                GameLayer::Weapon,
-                GameLayer::Player,
-            ]),
-            _marker: Enemy,
-        }
-    }
-
-    fn big_red_fish(iconset_assets: &IconsetAssets, pos: Vec3) -> EnemyBundle {
-        let game_sprite = GameSpriteBundle {
-            sprite: SpriteSheetBundle {
-                transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.04)),
-                sprite: TextureAtlasSprite::new(165),
-                texture_atlas: iconset_assets.iconset_fantasy_standalone.clone(),
-                ..Default::default()
-            },
-            base_rotation: BaseSpriteRotation(23.0 * PI / 14.0),
-            ..Default::default()
-        };
-
-        EnemyBundle {
-            game_sprite,
-            velocity: Velocity::default(),
-            rigid_body: RigidBody::Dynamic,
-            damping: Damping::from_linear(1.),
-            physic_material: PhysicMaterial { density: 10.0, ..Default::default() },
-            collision_shape: CollisionShape::Cuboid {
-                half_extends: Vec3::new(0.6, 0.6, 0.),
-                border_radius: None,
-            },
-            collision_layers: CollisionLayers::none().with_group(GameLayer::Enemies).with_masks(&[
-                GameLayer::Enemies,
-                GameLayer::Weapon,
-                GameLayer::Player,
-            ]),
-            _marker: Enemy,
-        }
-    }
-
-    fn pumpkin(iconset_assets: &IconsetAssets, pos: Vec3) -> EnemyBundle {
+    fn new(
+        kind: EnemyKind,
+        definition: &EnemyDefinition,
+        iconset_assets: &IconsetAssets,
+        pos: Vec3,
+        modifiers: EnemyModifiers,
+    ) -> EnemyBundle {
         let game_sprite = GameSpriteBundle {
             sprite: SpriteSheetBundle {
-                transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.02)),
-                sprite: TextureAtlasSprite::new(0),
-                texture_atlas: iconset_assets.iconset_halloween_standalone.clone(),
+                transform: Transform::from_translation(pos)
+                    .with_scale(Vec3::splat(modifiers.scale(definition))),
+                sprite: TextureAtlasSprite::new(definition.index),
+                texture_atlas: definition.atlas.atlas(iconset_assets),
                 ..Default::default()
             },
-            base_rotation: BaseSpriteRotation(6.),
-            ..Default::default()
+            base_rotation: BaseSpriteRotation(definition.rotation),
+            base_flip: BaseSpriteFlip { flip_x: definition.flip_x, ..Default::default() },
         };
 
         EnemyBundle {
             game_sprite,
             velocity: Velocity::default(),
             rigid_body: RigidBody::Dynamic,
-            damping: Damping::from_linear(1.),
-            physic_material: PhysicMaterial { density: 10.0, ..Default::default() },
-            collision_shape: CollisionShape::Cuboid {
-                half_extends: Vec3::new(0.3, 0.3, 0.),
-                border_radius: None,
-            },
-            collision_layers: CollisionLayers::none().with_group(GameLayer::Enemies).with_masks(&[
-                GameLayer::Enemies,
-                GameLayer::Weapon,
-                GameLayer::Player,
-            ]),
-            _marker: Enemy,
-        }
-    }
-
-    fn skeleton_head(iconset_assets: &IconsetAssets, pos: Vec3) -> EnemyBundle {
-        let game_sprite = GameSpriteBundle {
-            sprite: SpriteSheetBundle {
-                transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.02)),
-                sprite: TextureAtlasSprite::new(1),
-                texture_atlas: iconset_assets.iconset_halloween_standalone.clone(),
-                ..Default::default()
-            },
-            base_rotation: BaseSpriteRotation(6.),
-            base_flip: BaseSpriteFlip { flip_x: true, ..Default::default() },
-            ..Default::default()
-        };
-
-        EnemyBundle {
-            game_sprite,
-            velocity: Velocity::default(),
-            rigid_body: RigidBody::Dynamic,
-            damping: Damping::from_linear(1.),
-            physic_material: PhysicMaterial { density: 10.0, ..Default::default() },
-            collision_shape: CollisionShape::Cuboid {
-                half_extends: Vec3::new(0.3, 0.3, 0.),
-                border_radius: None,
-            },
-
-            collision_layers: CollisionLayers::none().with_group(GameLayer::Enemies).with_masks(&[
-                GameLayer::Enemies,
-                GameLayer::Weapon,
-                GameLayer::Player,
-            ]),
-            _marker: Enemy,
-        }
-    }
-
-    fn knife(iconset_assets: &IconsetAssets, pos: Vec3) -> EnemyBundle {
-        let game_sprite = GameSpriteBundle {
-            sprite: SpriteSheetBundle {
-                transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.02)),
-                sprite: TextureAtlasSprite::new(26),
-                texture_atlas: iconset_assets.iconset_halloween_standalone.clone(),
-                ..Default::default()
-            },
-            base_rotation: BaseSpriteRotation(23.0 * PI / 14.0),
-            ..Default::default()
-        };
-
-        EnemyBundle {
-            game_sprite,
-            velocity: Velocity::default(),
-            rigid_body: RigidBody::Dynamic,
-            damping: Damping::from_linear(1.),
-            physic_material: PhysicMaterial { density: 10.0, ..Default::default() },
-            collision_shape: CollisionShape::Cuboid {
-                half_extends: Vec3::new(0.3, 0.3, 0.),
-                border_radius: None,
-            },
-
+            damping: Damping::from_linear(definition.damping),
+            physic_material: PhysicMaterial { density: definition.density, ..Default::default() },
+            collision_shape: modifiers.collision_shape(definition),
             collision_layers: CollisionLayers::none().with_group(GameLayer::Enemies).with_masks(&[
                 GameLayer::Enemies,
                 GameLayer::Weapon,
                 GameLayer::Player,
+                GameLayer::Obstacle,
             ]),
+            kind,
+            stats: modifiers.stats(definition),
+            modifiers,
+            health: Health(modifiers.health(definition)),
+            status_effects: StatusEffects::default(),
             _marker: Enemy,
         }
     }
 }
 
+/// Spawns the waves of the timeline whose time came since the last frame,
+/// then the waves of the endless mode once the timeline is over and the summoned waves.
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_enemy_waves(
     mut commands: Commands,
     time: Res<Time>,
+    mut clock: ResMut<WaveClock>,
     iconset_assets: Res<IconsetAssets>,
-    mut enemy_waves_query: Query<(
-        &mut Timer,
-        &EnemyKind,
-        &EnemyWaveSize,
-        &EnemyWavesCount,
-        &MovementKind,
-    )>,
+    data_assets: Res<DataAssets>,
+    enemy_definitions: Res<Assets<EnemyDefinitions>>,
+    wave_timelines: Res<Assets<WaveTimeline>>,
+    mut summons: EventReader<SummonWave>,
+    mut grid: ResMut<EnemyGrid>,
     player_query: Query<&Transform, With<Player>>,
 ) {
     let player_transform = match player_query.iter().next() {
@@ -258,68 +334,131 @@ pub fn spawn_enemy_waves(
         None => return,
     };
 
+    let (definitions, timeline) =
+        match (enemy_definitions.get(&data_assets.enemies), wave_timelines.get(&data_assets.waves))
+        {
+            (Some(definitions), Some(timeline)) => (definitions, timeline),
+            _ => return,
+        };
+
+    let previous = clock.0;
+    clock.0 += time.delta_seconds();
+
     let mut rng = rand::thread_rng();
-    for (mut timer, kind, size, count, movement_kind) in enemy_waves_query.iter_mut() {
-        if timer.tick(time.delta()).just_finished() {
-            // spawn enemy wave with enemy kind
-            for _ in 0..count.0 {
-                let origin = Vec2::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
-                let origin = move_from_deadzone(origin, 10.0);
-                let offset = player_transform.translation + origin.extend(0.0);
-
-                // TODO use spawn_batch for better performances
-                for _ in 0..size.0 {
-                    let pos = match movement_kind {
-                        MovementKind::Tracking => {
-                            random_in_radius(&mut rng, offset, 3.).extend(90.)
-                        }
-                        MovementKind::SlowWalking => {
-                            let pos = random_in_radius(&mut rng, offset, 10.);
-                            move_from_deadzone(pos, 3.).extend(90.)
-                        }
-                        MovementKind::RunningGroup => {
-                            random_in_radius(&mut rng, offset, 10.).extend(90.)
-                        }
-                    };
+    let mut waves = Vec::new();
+    for wave in timeline.waves.iter() {
+        for _ in 0..wave.spawns_between(previous, clock.0) {
+            waves.push((wave.clone(), Difficulty::default(), None));
+        }
+    }
+    if let Some(endless) = &timeline.endless {
+        let endless_waves = endless.waves_between(&mut rng, timeline.end(), previous, clock.0);
+        waves.extend(endless_waves.into_iter().map(|(wave, difficulty)| (wave, difficulty, None)));
+    }
+    for summon in summons.iter() {
+        waves.push((summon.wave.clone(), Difficulty::default(), Some(summon.position)));
+    }
 
-                    let entity_id = match kind {
-                        EnemyKind::BlueFish => {
-                            commands.spawn_bundle(EnemyBundle::blue_fish(&iconset_assets, pos)).id()
-                        }
-                        EnemyKind::BigRedFish => commands
-                            .spawn_bundle(EnemyBundle::big_red_fish(&iconset_assets, pos))
-                            .id(),
-                        EnemyKind::Pumpkin => {
-                            commands.spawn_bundle(EnemyBundle::pumpkin(&iconset_assets, pos)).id()
-                        }
-                        EnemyKind::SkeletonHead => commands
-                            .spawn_bundle(EnemyBundle::skeleton_head(&iconset_assets, pos))
-                            .id(),
-                        EnemyKind::Knife => {
-                            commands.spawn_bundle(EnemyBundle::knife(&iconset_assets, pos)).id()
-                        }
-                    };
+    for (wave, difficulty, position) in waves {
+        // Unknown kinds are reported when the run starts.
+        let definition = match definitions.get(&wave.kind) {
+            Some(definition) => definition,
+            None => continue,
+        };
 
-                    match movement_kind {
-                        MovementKind::Tracking => {
-                            commands.entity(entity_id).insert(TrackingMovement)
-                        }
-                        MovementKind::SlowWalking => {
-                            commands.entity(entity_id).insert(SlowWalkingMovement)
-                        }
-                        MovementKind::RunningGroup => {
-                            let direction =
-                                (player_transform.translation.xy() - pos.xy()).normalize_or_zero();
-                            commands.entity(entity_id).insert(RunningGroupMovement { direction })
+        for _ in 0..wave.groups {
+            let offset = match position {
+                Some(position) => position.extend(0.0),
+                None => {
+                    let origin = Vec2::new(rng.gen_range(-10.0..10.0), rng.gen_range(-10.0..10.0));
+                    let origin = move_from_deadzone(origin, 10.0);
+                    player_transform.translation + origin.extend(0.0)
+                }
+            };
+
+            // The enemies of a ring are spread evenly, starting from a random angle.
+            let phase = rng.gen_range(0.0..2. * PI);
+
+            // TODO use spawn_batch for better performances
+            for i in 0..wave.group_size {
+                let angle = phase + 2. * PI * i as f32 / wave.group_size as f32;
+                let pos = match wave.movement {
+                    // The ring is formed around the player, whatever the spawn pattern of the wave.
+                    MovementKind::Encircle { radius, .. } => {
+                        let around = Vec2::new(angle.cos(), angle.sin()) * radius;
+                        (player_transform.translation.xy() + around).extend(90.)
+                    }
+                    _ => {
+                        // Avoid spawning enemies on top of each other, when possible.
+                        let mut pos = spawn_position(&mut rng, wave.pattern, offset);
+                        for _ in 1..SPAWN_ATTEMPTS {
+                            if grid.within_radius(pos.xy(), SPAWN_CLEARANCE).next().is_none() {
+                                break;
+                            }
+                            pos = spawn_position(&mut rng, wave.pattern, offset);
                         }
-                    };
+                        pos
+                    }
+                };
+
+                let elite = rng.gen_bool(difficulty.elite_chance as f64);
+                let modifiers = EnemyModifiers { difficulty, elite };
+                let bundle = EnemyBundle::new(
+                    wave.kind.clone(),
+                    definition,
+                    &iconset_assets,
+                    pos,
+                    modifiers,
+                );
+                let health = bundle.health.0;
+                let entity_id = commands.spawn_bundle(bundle).id();
+                grid.insert(entity_id, pos.xy());
+
+                if let Some(boss) = &wave.boss {
+                    commands.entity(entity_id).insert(Boss::new(boss.clone(), health));
                 }
+
+                match wave.movement {
+                    MovementKind::Tracking => commands.entity(entity_id).insert(TrackingMovement),
+                    MovementKind::SlowWalking => {
+                        commands.entity(entity_id).insert(SlowWalkingMovement)
+                    }
+                    MovementKind::RunningGroup => {
+                        let direction =
+                            (player_transform.translation.xy() - pos.xy()).normalize_or_zero();
+                        commands.entity(entity_id).insert(RunningGroupMovement { direction })
+                    }
+                    MovementKind::Ranged => commands.entity(entity_id).insert(RangedMovement {
+                        fire: Timer::from_seconds(RANGED_FIRE_COOLDOWN, true),
+                    }),
+                    MovementKind::Encircle { radius, shrink } => commands
+                        .entity(entity_id)
+                        .insert(EncircleMovement { angle, radius, shrink }),
+                    MovementKind::Charging => {
+                        commands.entity(entity_id).insert(ChargingMovement::default())
+                    }
+                    MovementKind::Flocking { separation, alignment, cohesion } => commands
+                        .entity(entity_id)
+                        .insert(FlockingMovement { separation, alignment, cohesion }),
+                };
             }
         }
     }
 }
 
+fn spawn_position<R: Rng>(rng: &mut R, pattern: SpawnPattern, offset: Vec3) -> Vec3 {
+    match pattern {
+        SpawnPattern::Cluster { radius } => random_in_radius(rng, offset, radius).extend(90.),
+        SpawnPattern::Scattered { radius, deadzone } => {
+            let pos = random_in_radius(rng, offset, radius);
+            move_from_deadzone(pos, deadzone).extend(90.)
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
 pub fn tracking_movement(
+    flow_field: Res<FlowField>,
     player_query: Query<&Transform, With<Player>>,
     mut ennemies_query: Query<
         (
@@ -328,6 +467,8 @@ pub fn tracking_movement(
             &mut TextureAtlasSprite,
             &BaseSpriteRotation,
             &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
         ),
         (With<TrackingMovement>, Without<Player>),
     >,
@@ -337,12 +478,15 @@ pub fn tracking_movement(
         None => return,
     };
 
-    for (mut velocity, mut transform, mut sprite, rotation, flip) in ennemies_query.iter_mut() {
+    for (mut velocity, mut transform, mut sprite, rotation, flip, stats, effects) in
+        ennemies_query.iter_mut()
+    {
         let player = player_transform.translation.xy();
         let enemy = transform.translation.xy();
-        let direction = (player - enemy).normalize_or_zero();
+        let direction = flow_field.direction(enemy, player);
         let strenght = player.distance(enemy).min(TRACKING_MAX_SPEED);
-        velocity.linear += (direction * strenght * TRACKING_SPEED).extend(0.);
+        let speed = TRACKING_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (direction * strenght * speed).extend(0.);
 
         let angle = angle_between(enemy, player);
         if enemy.x > player.x {
@@ -355,7 +499,9 @@ pub fn tracking_movement(
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn slow_walking_movement(
+    flow_field: Res<FlowField>,
     player_query: Query<&Transform, With<Player>>,
     mut ennemies_query: Query<
         (
@@ -364,6 +510,8 @@ pub fn slow_walking_movement(
             &mut TextureAtlasSprite,
             &BaseSpriteRotation,
             &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
         ),
         (With<SlowWalkingMovement>, Without<Player>),
     >,
@@ -373,12 +521,15 @@ pub fn slow_walking_movement(
         None => return,
     };
 
-    for (mut velocity, mut transform, mut sprite, rotation, flip) in ennemies_query.iter_mut() {
+    for (mut velocity, mut transform, mut sprite, rotation, flip, stats, effects) in
+        ennemies_query.iter_mut()
+    {
         let player = player_transform.translation.xy();
         let enemy = transform.translation.xy();
-        let direction = (player - enemy).normalize_or_zero();
+        let direction = flow_field.direction(enemy, player);
         let strenght = player.distance(enemy).min(SLOW_WALKING_MAX_SPEED);
-        velocity.linear += (direction * strenght * SLOW_WALKING_SPEED).extend(0.);
+        let speed = SLOW_WALKING_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (direction * strenght * speed).extend(0.);
 
         let angle = angle_between(enemy, player);
         if enemy.x > player.x {
@@ -391,6 +542,7 @@ pub fn slow_walking_movement(
     }
 }
 
+#[allow(clippy::type_complexity)]
 pub fn running_group_movement(
     player_query: Query<&Transform, With<Player>>,
     mut ennemies_query: Query<
@@ -401,6 +553,8 @@ pub fn running_group_movement(
             &mut RunningGroupMovement,
             &BaseSpriteRotation,
             &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
         ),
         Without<Player>,
     >,
@@ -410,7 +564,7 @@ pub fn running_group_movement(
         None => return,
     };
 
-    for (mut velocity, mut transform, mut sprite, mut movement, rotation, flip) in
+    for (mut velocity, mut transform, mut sprite, mut movement, rotation, flip, stats, effects) in
         ennemies_query.iter_mut()
     {
         let player = player_transform.translation.xy();
@@ -422,7 +576,298 @@ pub fn running_group_movement(
         }
 
         let direction = movement.direction;
-        velocity.linear += (direction * RUNNING_SPEED).extend(0.);
+        let speed = RUNNING_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (direction * speed).extend(0.);
+
+        let angle = angle_between(enemy, player);
+        if enemy.x > player.x {
+            sprite.flip_x = !flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + PI - rotation.0);
+        } else {
+            sprite.flip_x = flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + rotation.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn encircle_movement(
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut ennemies_query: Query<
+        (
+            &mut Velocity,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &mut EncircleMovement,
+            &BaseSpriteRotation,
+            &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    for (mut velocity, mut transform, mut sprite, mut movement, rotation, flip, stats, effects) in
+        ennemies_query.iter_mut()
+    {
+        // The ring turns slowly while it tightens, frozen enemies hold their place.
+        let delta = time.delta_seconds() * effects.speed_multiplier();
+        movement.radius = (movement.radius - movement.shrink * delta).max(0.);
+        movement.angle += ENCIRCLE_ORBIT_SPEED * delta;
+
+        let player = player_transform.translation.xy();
+        let enemy = transform.translation.xy();
+        let target =
+            player + Vec2::new(movement.angle.cos(), movement.angle.sin()) * movement.radius;
+        let direction = (target - enemy).normalize_or_zero();
+        let strenght = target.distance(enemy).min(ENCIRCLE_MAX_SPEED);
+        let speed = ENCIRCLE_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (direction * strenght * speed).extend(0.);
+
+        let angle = angle_between(enemy, player);
+        if enemy.x > player.x {
+            sprite.flip_x = !flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + PI - rotation.0);
+        } else {
+            sprite.flip_x = flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + rotation.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn flocking_movement(
+    flow_field: Res<FlowField>,
+    grid: Res<EnemyGrid>,
+    player_query: Query<&Transform, With<Player>>,
+    mut ennemies_query: Query<
+        (
+            Entity,
+            &mut Velocity,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &FlockingMovement,
+            &BaseSpriteRotation,
+            &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    // Only the other members of a school are followed, every enemy is avoided.
+    let headings: HashMap<Entity, Vec2> = ennemies_query
+        .iter()
+        .map(|(entity, velocity, ..)| (entity, velocity.linear.xy()))
+        .collect();
+
+    for (
+        entity,
+        mut velocity,
+        mut transform,
+        mut sprite,
+        movement,
+        rotation,
+        flip,
+        stats,
+        effects,
+    ) in ennemies_query.iter_mut()
+    {
+        let player = player_transform.translation.xy();
+        let enemy = transform.translation.xy();
+
+        let mut separation = Vec2::ZERO;
+        let mut heading = Vec2::ZERO;
+        let mut center = Vec2::ZERO;
+        let mut flockmates = 0;
+        for (other, position) in grid.within_radius(enemy, FLOCKING_NEIGHBOUR_RADIUS) {
+            if other == entity {
+                continue;
+            }
+
+            let away = enemy - position;
+            let distance = away.length();
+            if distance < FLOCKING_SEPARATION_RADIUS {
+                separation +=
+                    away.normalize_or_zero() * (1. - distance / FLOCKING_SEPARATION_RADIUS);
+            }
+            if let Some(other_heading) = headings.get(&other) {
+                heading += *other_heading;
+                center += position;
+                flockmates += 1;
+            }
+        }
+
+        let direction = flow_field.direction(enemy, player);
+        let mut steering = direction * player.distance(enemy).min(FLOCKING_MAX_SPEED);
+        steering += separation * movement.separation;
+        if flockmates > 0 {
+            let flockmates = flockmates as f32;
+            steering += (heading / flockmates).normalize_or_zero() * movement.alignment;
+            steering += (center / flockmates - enemy).normalize_or_zero() * movement.cohesion;
+        }
+        let speed = FLOCKING_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (steering * speed).extend(0.);
+
+        let angle = angle_between(enemy, player);
+        if enemy.x > player.x {
+            sprite.flip_x = !flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + PI - rotation.0);
+        } else {
+            sprite.flip_x = flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + rotation.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn charging_movement(
+    flow_field: Res<FlowField>,
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut ennemies_query: Query<
+        (
+            &mut Velocity,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &mut ChargingMovement,
+            &BaseSpriteRotation,
+            &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    for (mut velocity, mut transform, mut sprite, mut movement, rotation, flip, stats, effects) in
+        ennemies_query.iter_mut()
+    {
+        let player = player_transform.translation.xy();
+        let enemy = transform.translation.xy();
+
+        // Frozen enemies are stuck in their current phase.
+        let delta = time.delta().mul_f32(effects.speed_multiplier());
+        let finished = movement.timer.tick(delta).finished();
+
+        match movement.phase {
+            ChargePhase::Approaching => {
+                let direction = flow_field.direction(enemy, player);
+                let strenght = player.distance(enemy).min(CHARGING_MAX_SPEED);
+                let speed = CHARGING_SPEED * stats.speed * effects.speed_multiplier();
+                velocity.linear += (direction * strenght * speed).extend(0.);
+
+                if player.distance(enemy) <= CHARGING_TRIGGER_DISTANCE {
+                    movement.direction = (player - enemy).normalize_or_zero();
+                    movement.enter(ChargePhase::Telegraphing, CHARGING_TELEGRAPH_DURATION);
+                }
+            }
+            ChargePhase::Telegraphing => {
+                velocity.linear = Vec3::ZERO;
+                // Flash over the tint of the status effects, then give it back.
+                let flash = (movement.timer.percent() * CHARGING_TELEGRAPH_FLASHES * 2.) as usize;
+                sprite.color =
+                    if flash % 2 == 0 { CHARGING_TELEGRAPH_COLOR } else { effects.tint() };
+
+                if finished {
+                    sprite.color = effects.tint();
+                    movement.enter(ChargePhase::Dashing, CHARGING_DASH_DURATION);
+                }
+            }
+            ChargePhase::Dashing => {
+                let speed = CHARGING_DASH_SPEED * stats.speed * effects.speed_multiplier();
+                velocity.linear = (movement.direction * speed).extend(0.);
+
+                if finished {
+                    movement.enter(ChargePhase::Recovering, CHARGING_RECOVERY_DURATION);
+                }
+            }
+            ChargePhase::Recovering => {
+                let brake = (CHARGING_RECOVERY_BRAKE * time.delta_seconds()).min(1.);
+                velocity.linear *= 1. - brake;
+
+                if finished {
+                    movement.enter(ChargePhase::Approaching, 0.);
+                }
+            }
+        }
+
+        // Keep facing the locked direction until the dash is over.
+        let target = match movement.phase {
+            ChargePhase::Telegraphing | ChargePhase::Dashing => enemy + movement.direction,
+            ChargePhase::Approaching | ChargePhase::Recovering => player,
+        };
+        let angle = angle_between(enemy, target);
+        if enemy.x > target.x {
+            sprite.flip_x = !flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + PI - rotation.0);
+        } else {
+            sprite.flip_x = flip.flip_x;
+            transform.rotation = Quat::from_rotation_z(angle + rotation.0);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn ranged_movement(
+    flow_field: Res<FlowField>,
+    mut commands: Commands,
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    mut ennemies_query: Query<
+        (
+            &mut Velocity,
+            &mut Transform,
+            &mut TextureAtlasSprite,
+            &mut RangedMovement,
+            &BaseSpriteRotation,
+            &BaseSpriteFlip,
+            &EnemyStats,
+            &StatusEffects,
+        ),
+        Without<Player>,
+    >,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    for (mut velocity, mut transform, mut sprite, mut movement, rotation, flip, stats, effects) in
+        ennemies_query.iter_mut()
+    {
+        let player = player_transform.translation.xy();
+        let enemy = transform.translation.xy();
+        let direction = flow_field.direction(enemy, player);
+        let distance = player.distance(enemy);
+
+        // Get closer when too far from the player and back off when too close.
+        let strenght =
+            (distance - RANGED_PREFERRED_DISTANCE).clamp(-RANGED_MAX_SPEED, RANGED_MAX_SPEED);
+        let speed = RANGED_SPEED * stats.speed * effects.speed_multiplier();
+        velocity.linear += (direction * strenght * speed).extend(0.);
+
+        // Frozen enemies don't fire.
+        let fire = movement.fire.tick(time.delta()).just_finished();
+        if fire && distance <= RANGED_FIRE_RANGE && effects.speed_multiplier() > 0. {
+            let aim = (player - enemy).normalize_or_zero();
+            spawn_enemy_projectile(&mut commands, enemy, aim, stats.contact_damage);
+        }
 
         let angle = angle_between(enemy, player);
         if enemy.x > player.x {
@@ -434,3 +879,50 @@ pub fn running_group_movement(
         }
     }
 }
+
+pub fn spawn_enemy_projectile(
+    commands: &mut Commands,
+    position: Vec2,
+    direction: Vec2,
+    damage: usize,
+) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform::from_translation(position.extend(95.)),
+            sprite: Sprite {
+                color: ENEMY_PROJECTILE_COLOR,
+                custom_size: Some(Vec2::splat(ENEMY_PROJECTILE_RADIUS * 2.)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius: ENEMY_PROJECTILE_RADIUS })
+        .insert(
+            CollisionLayers::none()
+                .with_group(GameLayer::EnemyProjectile)
+                .with_mask(GameLayer::Player),
+        )
+        .insert(Projectile {
+            velocity: direction * ENEMY_PROJECTILE_SPEED,
+            hits_left: 1,
+            lifetime: Timer::from_seconds(ENEMY_PROJECTILE_LIFETIME, false),
+        })
+        .insert(EnemyProjectile { damage });
+}
+
+/// Despawns the enemy projectiles touching the player, the damage is dealt by `enemies_damage_player`.
+pub fn enemy_projectiles_hit_player(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+) {
+    for event in events.iter().filter(|e| e.is_started()) {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        if is_enemy_projectile_layer(layers_1) && is_player_layer(layers_2) {
+            commands.entity(entity_1).despawn();
+        } else if is_enemy_projectile_layer(layers_2) && is_player_layer(layers_1) {
+            commands.entity(entity_2).despawn();
+        }
+    }
+}
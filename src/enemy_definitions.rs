@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use heron::prelude::*;
+use serde::Deserialize;
+
+use crate::assets::*;
+use crate::enemies::{EnemyKind, EnemyModifiers, EnemyStats};
+use crate::game_sprites::*;
+
+/// The texture atlases an enemy sprite can be picked from.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Iconset {
+    FantasyStandalone,
+    FantasyCastshadows,
+    HalloweenStandalone,
+}
+
+impl Iconset {
+    pub fn atlas(&self, iconset_assets: &IconsetAssets) -> Handle<TextureAtlas> {
+        match self {
+            Iconset::FantasyStandalone => iconset_assets.iconset_fantasy_standalone.clone(),
+            Iconset::FantasyCastshadows => iconset_assets.iconset_fantasy_castshadows.clone(),
+            Iconset::HalloweenStandalone => iconset_assets.iconset_halloween_standalone.clone(),
+        }
+    }
+}
+
+/// The archetype of an enemy, every enemy of the same kind is spawned from it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnemyDefinition {
+    pub atlas: Iconset,
+    pub index: usize,
+    pub scale: f32,
+    /// The rotation of the sprite that makes it face right, in radian.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub flip_x: bool,
+    /// The half extents of the collision box.
+    pub hitbox: (f32, f32),
+    pub health: usize,
+    /// The amount of health the player loses when touched by this kind of enemy.
+    pub damage: usize,
+    /// The factor applied to the speed of the movement of the enemy.
+    pub speed: f32,
+    pub density: f32,
+    pub damping: f32,
+    /// How much of the knockback of weapons this kind of enemy ignores, between 0 and 1.
+    #[serde(default)]
+    pub knockback_resistance: f32,
+    /// The amount of xp given by the gem dropped by this kind of enemy.
+    pub xp: usize,
+}
+
+impl EnemyDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if !(0. ..=1.).contains(&self.knockback_resistance) {
+            return Err(format!(
+                "the knockback resistance must be between 0 and 1, got {}",
+                self.knockback_resistance
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The enemy archetypes, loaded from a `.enemies.ron` file.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "03def2b6-890e-418e-8611-f3eda9a99377"]
+pub struct EnemyDefinitions(pub HashMap<EnemyKind, EnemyDefinition>);
+
+impl EnemyDefinitions {
+    pub fn get(&self, kind: &EnemyKind) -> Option<&EnemyDefinition> {
+        self.0.get(kind)
+    }
+}
+
+#[derive(Default)]
+pub struct EnemyDefinitionsLoader;
+
+impl AssetLoader for EnemyDefinitionsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut definitions: EnemyDefinitions = ron::de::from_bytes(bytes)?;
+
+            // A bad definition is skipped instead of failing the whole file.
+            let path = load_context.path().display().to_string();
+            definitions.0.retain(|kind, definition| match definition.validate() {
+                Ok(()) => true,
+                Err(reason) => {
+                    error!("Ignoring enemy {} of {}: {}", kind.0, path, reason);
+                    false
+                }
+            });
+            load_context.set_default_asset(LoadedAsset::new(definitions));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["enemies.ron"]
+    }
+}
+
+/// Applies the modified definitions to the live enemies of each kind,
+/// the enemies spawned afterwards read the new definitions on their own.
+///
+/// The current health of the enemies is kept as it is,
+/// the difficulty and elite modifiers are applied again on top of the new definitions.
+#[allow(clippy::type_complexity)]
+pub fn reload_enemy_definitions(
+    mut events: EventReader<AssetEvent<EnemyDefinitions>>,
+    iconset_assets: Option<Res<IconsetAssets>>,
+    enemy_definitions: Res<Assets<EnemyDefinitions>>,
+    mut enemies_query: Query<(
+        &EnemyKind,
+        &EnemyModifiers,
+        &mut EnemyStats,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+        &mut Handle<TextureAtlas>,
+        &mut BaseSpriteRotation,
+        &mut BaseSpriteFlip,
+        &mut Damping,
+        &mut PhysicMaterial,
+        &mut CollisionShape,
+    )>,
+) {
+    let iconset_assets = match iconset_assets {
+        Some(iconset_assets) => iconset_assets,
+        None => return,
+    };
+
+    for event in events.iter() {
+        let definitions = match event {
+            AssetEvent::Modified { handle } => match enemy_definitions.get(handle) {
+                Some(definitions) => definitions,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        info!("Reloaded {} enemy definitions", definitions.0.len());
+        for (
+            kind,
+            modifiers,
+            mut stats,
+            mut transform,
+            mut sprite,
+            mut atlas,
+            mut rotation,
+            mut flip,
+            mut damping,
+            mut material,
+            mut shape,
+        ) in enemies_query.iter_mut()
+        {
+            let definition = match definitions.get(kind) {
+                Some(definition) => definition,
+                None => continue,
+            };
+
+            *stats = modifiers.stats(definition);
+            transform.scale = Vec3::splat(modifiers.scale(definition));
+            sprite.index = definition.index;
+            *atlas = definition.atlas.atlas(&iconset_assets);
+            rotation.0 = definition.rotation;
+            flip.flip_x = definition.flip_x;
+            *damping = Damping::from_linear(definition.damping);
+            material.density = definition.density;
+            *shape = modifiers.collision_shape(definition);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knockback_resistance_validation() {
+        let mut definition = EnemyDefinition {
+            atlas: Iconset::FantasyStandalone,
+            index: 0,
+            scale: 1.,
+            rotation: 0.,
+            flip_x: false,
+            hitbox: (0.5, 0.5),
+            health: 1,
+            damage: 1,
+            speed: 1.,
+            density: 1.,
+            damping: 1.,
+            knockback_resistance: 0.5,
+            xp: 1,
+        };
+        assert!(definition.validate().is_ok());
+        definition.knockback_resistance = 1.;
+        assert!(definition.validate().is_ok());
+
+        for resistance in [1.5, -0.5, f32::NAN] {
+            definition.knockback_resistance = resistance;
+            assert!(definition.validate().is_err(), "{} was accepted", resistance);
+        }
+    }
+}
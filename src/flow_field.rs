@@ -0,0 +1,239 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::{GameLayer, Player, MAP_SIZE};
+
+const FLOW_FIELD_STRAIGHT_COST: u32 = 10;
+const FLOW_FIELD_DIAGONAL_COST: u32 = 14;
+
+const FLOW_FIELD_DIRECTIONS: [(i32, i32); 8] =
+    [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// The walking distance to the player from every cell of the map, around the obstacles,
+/// computed once for all the enemies to find their way to the player.
+///
+/// The cells are one unit wide, like the lines of the map.
+#[derive(Default)]
+pub struct FlowField {
+    /// The cell of the player when the field was computed, none when out of the map.
+    target: Option<(i32, i32)>,
+    /// The obstacles the field was computed around.
+    obstacles: Vec<Entity>,
+    blocked: Vec<bool>,
+    /// Empty when there is no obstacle, the straight line is the shortest path then.
+    distances: Vec<u32>,
+}
+
+impl FlowField {
+    fn clamped_cell(position: Vec2) -> (i32, i32) {
+        let cell = (position + Vec2::splat(MAP_SIZE as f32 / 2.)).floor();
+        let max = MAP_SIZE as i32 - 1;
+        (cell.x.clamp(0., max as f32) as i32, cell.y.clamp(0., max as f32) as i32)
+    }
+
+    fn cell(position: Vec2) -> Option<(i32, i32)> {
+        let half = Vec2::splat(MAP_SIZE as f32 / 2.);
+        if position.cmpge(-half).all() && position.cmplt(half).all() {
+            Some(Self::clamped_cell(position))
+        } else {
+            None
+        }
+    }
+
+    fn index((x, y): (i32, i32)) -> usize {
+        (y * MAP_SIZE as i32 + x) as usize
+    }
+
+    fn center((x, y): (i32, i32)) -> Vec2 {
+        Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - Vec2::splat(MAP_SIZE as f32 / 2.)
+    }
+
+    fn walkable(&self, (x, y): (i32, i32)) -> bool {
+        let size = MAP_SIZE as i32;
+        (0..size).contains(&x) && (0..size).contains(&y) && !self.blocked[Self::index((x, y))]
+    }
+
+    /// Returns the walkable cells around `cell` with the cost to step on them.
+    fn neighbours(&self, (x, y): (i32, i32)) -> impl Iterator<Item = ((i32, i32), u32)> + '_ {
+        FLOW_FIELD_DIRECTIONS.iter().filter_map(move |&(dx, dy)| {
+            let next = (x + dx, y + dy);
+            if !self.walkable(next) {
+                None
+            } else if dx == 0 || dy == 0 {
+                Some((next, FLOW_FIELD_STRAIGHT_COST))
+            } else if self.walkable((x + dx, y)) && self.walkable((x, y + dy)) {
+                Some((next, FLOW_FIELD_DIAGONAL_COST))
+            } else {
+                // Don't cut the corners of the obstacles.
+                None
+            }
+        })
+    }
+
+    /// Computes the distances from every cell to the `target` cell, going around the `blocked` cells.
+    fn compute(&mut self, target: Option<(i32, i32)>, blocked: Vec<bool>) {
+        self.target = target;
+        self.distances.clear();
+        let target = match target {
+            Some(target) if blocked.iter().any(|b| *b) => target,
+            _ => return,
+        };
+
+        self.blocked = blocked;
+        self.distances.resize(self.blocked.len(), u32::MAX);
+        self.distances[Self::index(target)] = 0;
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, target)));
+        while let Some(Reverse((distance, cell))) = queue.pop() {
+            if distance > self.distances[Self::index(cell)] {
+                continue;
+            }
+
+            let neighbours: Vec<_> = self.neighbours(cell).collect();
+            for (next, cost) in neighbours {
+                let next_distance = distance + cost;
+                if next_distance < self.distances[Self::index(next)] {
+                    self.distances[Self::index(next)] = next_distance;
+                    queue.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+    }
+
+    /// Returns the direction to follow from `position` to reach the player at `player`.
+    ///
+    /// The enemies go straight to the player when there is no obstacle,
+    /// when they are out of the map and once they reached the cell of the player.
+    pub fn direction(&self, position: Vec2, player: Vec2) -> Vec2 {
+        let straight = (player - position).normalize_or_zero();
+        let cell = match Self::cell(position) {
+            Some(cell) if !self.distances.is_empty() && Some(cell) != self.target => cell,
+            _ => return straight,
+        };
+
+        let distance = self.distances[Self::index(cell)];
+        match self.neighbours(cell).min_by_key(|(next, _)| self.distances[Self::index(*next)]) {
+            Some((next, _)) if self.distances[Self::index(next)] < distance => {
+                (Self::center(next) - position).normalize_or_zero()
+            }
+            // Unreachable cells, like the ones walled in.
+            _ => straight,
+        }
+    }
+}
+
+/// Computes the flow field again when the player moves to another cell
+/// and when obstacles are added or removed.
+pub fn update_flow_field(
+    mut flow_field: ResMut<FlowField>,
+    player_query: Query<&Transform, With<Player>>,
+    obstacles_query: Query<
+        (Entity, &Transform, &CollisionShape, &CollisionLayers),
+        With<RigidBody>,
+    >,
+    added_query: Query<&CollisionLayers, (Added<CollisionLayers>, With<RigidBody>)>,
+) {
+    let target = match player_query.iter().next() {
+        Some(transform) => FlowField::cell(transform.translation.xy()),
+        None => return,
+    };
+    let added = added_query.iter().any(|layers| layers.contains_group(GameLayer::Obstacle));
+    // Looked up instead of using `RemovedComponents`, which misses the despawns of the previous frame.
+    let removed = flow_field.obstacles.iter().any(|entity| obstacles_query.get(*entity).is_err());
+    if target == flow_field.target && !added && !removed {
+        return;
+    }
+
+    // The obstacles are handled as axis aligned boxes.
+    let mut blocked = vec![false; (MAP_SIZE * MAP_SIZE) as usize];
+    flow_field.obstacles.clear();
+    for (entity, transform, shape, layers) in obstacles_query.iter() {
+        if !layers.contains_group(GameLayer::Obstacle) {
+            continue;
+        }
+        flow_field.obstacles.push(entity);
+
+        let half_size = match shape {
+            CollisionShape::Cuboid { half_extends, .. } => half_extends.xy(),
+            CollisionShape::Sphere { radius } => Vec2::splat(*radius),
+            _ => continue,
+        };
+        let position = transform.translation.xy();
+        let map_half_size = Vec2::splat(MAP_SIZE as f32 / 2.);
+        if (position + half_size).cmplt(-map_half_size).any()
+            || (position - half_size).cmpge(map_half_size).any()
+        {
+            continue;
+        }
+
+        let (min_x, min_y) = FlowField::clamped_cell(position - half_size);
+        let (max_x, max_y) = FlowField::clamped_cell(position + half_size);
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                blocked[FlowField::index((x, y))] = true;
+            }
+        }
+    }
+
+    flow_field.compute(target, blocked);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocked(cells: impl IntoIterator<Item = (i32, i32)>) -> Vec<bool> {
+        let mut blocked = vec![false; (MAP_SIZE * MAP_SIZE) as usize];
+        for cell in cells {
+            blocked[FlowField::index(cell)] = true;
+        }
+        blocked
+    }
+
+    #[test]
+    fn goes_straight_without_obstacles() {
+        let mut flow_field = FlowField::default();
+        flow_field.compute(Some((10, 20)), blocked([]));
+        let position = FlowField::center((20, 20));
+        let player = FlowField::center((10, 20));
+        assert_eq!(flow_field.direction(position, player), Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn routes_around_a_wall() {
+        // A wall between the enemy and the player, open at the top of the map.
+        let mut flow_field = FlowField::default();
+        flow_field.compute(Some((10, 20)), blocked((0..=30).map(|y| (15, y))));
+        let position = FlowField::center((20, 20));
+        let player = FlowField::center((10, 20));
+
+        let direction = flow_field.direction(position, player);
+        assert!(direction.y > 0., "{:?} doesn't go around the wall", direction);
+
+        // Following the field reaches the player without crossing the wall.
+        let mut cell = (20, 20);
+        for _ in 0..100 {
+            if cell == (10, 20) {
+                return;
+            }
+            let direction = flow_field.direction(FlowField::center(cell), player);
+            cell = FlowField::clamped_cell(FlowField::center(cell) + direction);
+            assert!(flow_field.walkable(cell), "stepped on the wall at {:?}", cell);
+        }
+        panic!("never reached the player, stuck at {:?}", cell);
+    }
+
+    #[test]
+    fn walled_in_cells_go_straight() {
+        let mut flow_field = FlowField::default();
+        let walls = FLOW_FIELD_DIRECTIONS.iter().map(|(dx, dy)| (20 + dx, 20 + dy));
+        flow_field.compute(Some((10, 20)), blocked(walls));
+        let position = FlowField::center((20, 20));
+        let player = FlowField::center((10, 20));
+        assert_eq!(flow_field.direction(position, player), Vec2::new(-1., 0.));
+    }
+}
@@ -1,147 +1,234 @@
-use std::f32::consts::PI;
-
 use bevy::math::Vec3Swizzles;
 use bevy::prelude::*;
+use bevy::utils::HashSet;
 use bevy_asset_loader::AssetLoader;
 use heron::prelude::*;
 
 use self::assets::*;
+use self::bosses::*;
+use self::damage_numbers::*;
 use self::enemies::*;
+use self::enemy_definitions::*;
+use self::flow_field::*;
 use self::helper::*;
+use self::screens::*;
+use self::spatial::*;
+use self::status_effects::*;
+use self::upgrades::*;
+use self::waves::*;
+use self::weapons::*;
 
 mod assets;
+mod bosses;
+mod damage_numbers;
 mod enemies;
+mod enemy_definitions;
+mod flow_field;
 mod game_sprites;
 mod helper;
+mod screens;
+mod spatial;
+mod status_effects;
+mod upgrades;
+mod waves;
+mod weapons;
 
 const MAP_SIZE: u32 = 41;
 const GRID_WIDTH: f32 = 0.05;
+const CAMERA_SCALE: f32 = 1. / 50.;
 const PLAYER_SPEED: f32 = 10.0;
+const PLAYER_HEALTH: usize = 100;
+const PLAYER_MAGNET_RADIUS: f32 = 2.0;
 
 const HEALTHY_PLAYER_COLOR: Color = Color::rgb(0., 0.47, 1.);
 const HIT_PLAYER_COLOR: Color = Color::rgb(0.9, 0.027, 0.);
+const PLAYER_BLINK_PERIOD: f32 = 0.1; // seconds
 
-const AXE_HEAD_COLOR: Color = Color::rgb(0.52, 0.62, 0.8);
-const AXE_HEAD_SPEED: f32 = 2.; // radian/s
+const FISHING_ROD_LEVEL: usize = 3;
 
 fn main() {
     let mut app = App::new();
     AssetLoader::new(MyStates::AssetLoading)
-        .continue_to_state(MyStates::Next)
+        .continue_to_state(MyStates::MainMenu)
         .with_collection::<IconsetAssets>()
+        .with_collection::<FontAssets>()
+        .with_collection::<DataAssets>()
         .build(&mut app);
 
     app.add_state(MyStates::AssetLoading)
         .insert_resource(ClearColor(Color::rgb(0.53, 0.53, 0.53)))
         .add_plugins(DefaultPlugins)
         .add_plugin(PhysicsPlugin::default())
+        .add_asset::<EnemyDefinitions>()
+        .init_asset_loader::<EnemyDefinitionsLoader>()
+        .add_asset::<WaveTimeline>()
+        .init_asset_loader::<WaveTimelineLoader>()
         .insert_resource(Gravity::from(Vec3::ZERO))
-        .add_startup_system(setup)
-        .add_startup_system(spawn_player)
+        .init_resource::<PlayerHitSettings>()
+        .init_resource::<XpCurve>()
+        .init_resource::<EnemyGrid>()
+        .init_resource::<FlowField>()
+        .add_event::<LevelUp>()
+        .add_event::<WeaponHit>()
+        .add_event::<EnemyKilled>()
+        .add_event::<EnemyDamaged>()
+        .add_event::<SummonWave>()
+        .add_startup_system(setup_cameras)
+        .add_startup_system(watch_asset_changes)
+        .add_startup_system(setup_weapon_textures)
+        .add_system(reload_enemy_definitions)
+        .add_system(reload_wave_timeline)
         .add_system_to_stage(CoreStage::PostUpdate, camera_follow)
+        .add_system_set(SystemSet::on_enter(MyStates::MainMenu).with_system(setup_main_menu))
+        .add_system_set(SystemSet::on_update(MyStates::MainMenu).with_system(main_menu_input))
+        .add_system_set(
+            SystemSet::on_exit(MyStates::MainMenu).with_system(despawn_screen::<MainMenuScreen>),
+        )
         .add_system_set(
-            SystemSet::on_update(MyStates::Next)
-                .with_system(move_player)
+            SystemSet::on_enter(MyStates::Playing).with_system(setup).with_system(spawn_player),
+        )
+        .add_system_set(
+            SystemSet::on_update(MyStates::Playing)
+                .with_system(pause_input)
+                .with_system(tick_run_stats)
+                .with_system(move_player.before(PlayerHit))
                 .with_system(tracking_movement)
                 .with_system(slow_walking_movement)
                 .with_system(running_group_movement)
+                .with_system(ranged_movement)
+                .with_system(encircle_movement)
+                .with_system(charging_movement.after(StatusEffectsTint))
+                .with_system(flocking_movement)
                 .with_system(spawn_enemy_waves)
+                .with_system(update_enemy_grid)
+                .with_system(update_flow_field)
                 .with_system(create_loot)
                 .with_system(player_loot_stuff)
-                .with_system(axe_head_kill_ennemies)
-                .with_system(rotate_axe_head)
+                .with_system(weapon_hitboxes_hit_enemies)
+                .with_system(weapons_damage_enemies)
+                .with_system(drop_gems)
+                .with_system(apply_status_effects)
+                .with_system(tick_status_effects.label(StatusEffectsTint))
+                .with_system(spawn_damage_numbers)
+                .with_system(animate_damage_numbers)
+                .with_system(tick_weapons_cooldown)
+                .with_system(sync_axe_heads)
+                .with_system(rotate_axe_heads)
+                .with_system(fire_magic_bolts)
+                .with_system(move_projectiles)
+                .with_system(track_aura_contacts)
+                .with_system(sync_auras)
+                .with_system(auras_hit_enemies)
+                .with_system(fire_chain_lightnings)
+                .with_system(fade_lightning_arcs)
+                .with_system(boss_attacks)
+                .with_system(enemy_projectiles_hit_player)
+                .with_system(sync_boss_health_bar)
+                .with_system(drop_boss_chests)
                 .with_system(change_player_color)
+                .with_system(enemies_damage_player.label(PlayerHit))
+                .with_system(tick_player_hit_reaction)
                 .with_system(player_loot_gems)
+                .with_system(player_level_up)
+                .with_system(queue_upgrade_selection)
                 .with_system(gems_player_attraction),
         )
+        .add_system_set(SystemSet::on_exit(MyStates::Playing).with_system(cleanup_run))
+        .add_system_set(SystemSet::on_enter(MyStates::Paused).with_system(setup_pause_menu))
+        .add_system_set(SystemSet::on_update(MyStates::Paused).with_system(pause_menu_input))
+        .add_system_set(SystemSet::on_exit(MyStates::Paused).with_system(exit_pause_menu))
+        .add_system_set(
+            SystemSet::on_enter(MyStates::UpgradeSelection).with_system(setup_upgrade_selection),
+        )
+        .add_system_set(
+            SystemSet::on_update(MyStates::UpgradeSelection).with_system(upgrade_selection_input),
+        )
+        .add_system_set(
+            SystemSet::on_exit(MyStates::UpgradeSelection).with_system(exit_upgrade_selection),
+        )
+        .add_system_set(SystemSet::on_enter(MyStates::GameOver).with_system(setup_game_over))
+        .add_system_set(SystemSet::on_update(MyStates::GameOver).with_system(game_over_input))
+        .add_system_set(
+            SystemSet::on_exit(MyStates::GameOver).with_system(despawn_screen::<GameOverScreen>),
+        )
         .run();
 }
 
-fn setup(mut commands: Commands) {
+fn setup_cameras(mut commands: Commands) {
     let mut camera_bundle = OrthographicCameraBundle::new_2d();
-    camera_bundle.orthographic_projection.scale = 1. / 50.;
+    camera_bundle.orthographic_projection.scale = CAMERA_SCALE;
     commands.spawn_bundle(camera_bundle);
-    commands.insert_resource(LootAllGemsFor(Timer::from_seconds(0., false)));
+    commands.spawn_bundle(UiCameraBundle::default());
+}
+
+/// Reloads the modified assets, like the enemy and wave definitions, while the game runs.
+fn watch_asset_changes(asset_server: Res<AssetServer>) {
+    if cfg!(debug_assertions) {
+        asset_server.watch_for_changes().unwrap();
+    }
+}
 
-    // Setup enemy waves
-    commands.spawn_bundle(EnemyWaveBundle {
-        kind: EnemyKind::BlueFish,
-        timer: Timer::from_seconds(3., false),
-        size: EnemyWaveSize(40),
-        count: EnemyWavesCount(2),
-        movement_kind: MovementKind::Tracking,
-    });
-
-    commands.spawn_bundle(EnemyWaveBundle {
-        kind: EnemyKind::Pumpkin,
-        timer: Timer::from_seconds(10., true),
-        size: EnemyWaveSize(10),
-        count: EnemyWavesCount(3),
-        movement_kind: MovementKind::SlowWalking,
-    });
-
-    commands.spawn_bundle(EnemyWaveBundle {
-        kind: EnemyKind::SkeletonHead,
-        timer: Timer::from_seconds(15., false),
-        size: EnemyWaveSize(30),
-        count: EnemyWavesCount(1),
-        movement_kind: MovementKind::RunningGroup,
-    });
-
-    commands.spawn_bundle(EnemyWaveBundle {
-        kind: EnemyKind::BigRedFish,
-        timer: Timer::from_seconds(25., false),
-        size: EnemyWaveSize(30),
-        count: EnemyWavesCount(2),
-        movement_kind: MovementKind::Tracking,
-    });
-
-    commands.spawn_bundle(EnemyWaveBundle {
-        kind: EnemyKind::Knife,
-        timer: Timer::from_seconds(35., false),
-        size: EnemyWaveSize(40),
-        count: EnemyWavesCount(2),
-        movement_kind: MovementKind::RunningGroup,
-    });
+fn setup(
+    mut commands: Commands,
+    data_assets: Res<DataAssets>,
+    enemy_definitions: Res<Assets<EnemyDefinitions>>,
+    wave_timelines: Res<Assets<WaveTimeline>>,
+) {
+    commands.insert_resource(LootAllGemsFor(Timer::from_seconds(0., false)));
+    commands.insert_resource(RunStats::default());
+    commands.insert_resource(PendingUpgrades::default());
+    commands.insert_resource(DamageNumberPool::default());
+    commands.insert_resource(WaveClock::default());
+    commands.insert_resource(PlayerContacts::default());
+
+    if let (Some(definitions), Some(timeline)) =
+        (enemy_definitions.get(&data_assets.enemies), wave_timelines.get(&data_assets.waves))
+    {
+        timeline.check_kinds(definitions);
+    }
 
     // Horizontal lines
     for i in 0..=MAP_SIZE {
-        commands.spawn_bundle(SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                0.,
-                i as f32 - MAP_SIZE as f32 / 2.,
-                10.,
-            )),
-            sprite: Sprite {
-                color: Color::rgb(0.27, 0.27, 0.27),
-                custom_size: Some(Vec2::new(MAP_SIZE as f32, GRID_WIDTH)),
+        commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform::from_translation(Vec3::new(
+                    0.,
+                    i as f32 - MAP_SIZE as f32 / 2.,
+                    10.,
+                )),
+                sprite: Sprite {
+                    color: Color::rgb(0.27, 0.27, 0.27),
+                    custom_size: Some(Vec2::new(MAP_SIZE as f32, GRID_WIDTH)),
+                    ..Default::default()
+                },
                 ..Default::default()
-            },
-            ..Default::default()
-        });
+            })
+            .insert(GridLine);
     }
 
     // Vertical lines
     for i in 0..=MAP_SIZE {
-        commands.spawn_bundle(SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                i as f32 - MAP_SIZE as f32 / 2.,
-                0.,
-                10.,
-            )),
-            sprite: Sprite {
-                color: Color::rgb(0.27, 0.27, 0.27),
-                custom_size: Some(Vec2::new(GRID_WIDTH, MAP_SIZE as f32)),
+        commands
+            .spawn_bundle(SpriteBundle {
+                transform: Transform::from_translation(Vec3::new(
+                    i as f32 - MAP_SIZE as f32 / 2.,
+                    0.,
+                    10.,
+                )),
+                sprite: Sprite {
+                    color: Color::rgb(0.27, 0.27, 0.27),
+                    custom_size: Some(Vec2::new(GRID_WIDTH, MAP_SIZE as f32)),
+                    ..Default::default()
+                },
                 ..Default::default()
-            },
-            ..Default::default()
-        });
+            })
+            .insert(GridLine);
     }
 }
 
-fn spawn_player(mut commands: Commands) {
+fn spawn_player(mut commands: Commands, weapon_textures: Res<WeaponTextures>) {
     let player_pos = Vec3::new(0., 0., 100.);
-    commands
+    let player = commands
         .spawn_bundle(SpriteBundle {
             transform: Transform::from_translation(player_pos),
             sprite: Sprite {
@@ -159,33 +246,22 @@ fn spawn_player(mut commands: Commands) {
             GameLayer::Gem,
             GameLayer::Stuff,
             GameLayer::Enemies,
+            GameLayer::EnemyProjectile,
+            GameLayer::Obstacle,
         ]))
         .insert(Player::default())
-        .with_children(|commands| {
-            commands
-                .spawn_bundle(SpriteBundle {
-                    transform: Transform::from_translation(Vec3::new(0., -3., 0.)),
-                    sprite: Sprite {
-                        color: AXE_HEAD_COLOR,
-                        custom_size: Some(Vec2::new(0.8, 0.8)),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(RigidBody::Static)
-                .insert(CollisionShape::Sphere { radius: 0.6 })
-                .insert(
-                    CollisionLayers::none()
-                        .with_group(GameLayer::Weapon)
-                        .with_mask(GameLayer::Enemies),
-                )
-                .insert(RotationRadian(0.))
-                .insert(AxeHead);
-        });
+        .insert(Health(PLAYER_HEALTH))
+        .insert(PlayerStats::default())
+        .id();
+
+    add_weapon(&mut commands, &weapon_textures, player, WeaponKind::AxeHead);
 }
 
-fn move_player(keys: Res<Input<KeyCode>>, mut player_query: Query<&mut Velocity, With<Player>>) {
-    for mut velocity in player_query.iter_mut() {
+fn move_player(
+    keys: Res<Input<KeyCode>>,
+    mut player_query: Query<(&mut Velocity, &PlayerStats), Without<Knockback>>,
+) {
+    for (mut velocity, stats) in player_query.iter_mut() {
         let y = if keys.any_pressed([KeyCode::Up, KeyCode::W]) {
             1.
         } else if keys.any_pressed([KeyCode::Down, KeyCode::S]) {
@@ -202,67 +278,173 @@ fn move_player(keys: Res<Input<KeyCode>>, mut player_query: Query<&mut Velocity,
             0.
         };
 
-        velocity.linear = Vec2::new(x, y).normalize_or_zero().extend(0.) * PLAYER_SPEED;
+        velocity.linear = Vec2::new(x, y).normalize_or_zero().extend(0.) * stats.speed;
     }
 }
 
-fn rotate_axe_head(
-    time: Res<Time>,
-    mut axe_head_query: Query<
-        (&mut Transform, &mut RotationRadian),
-        (With<AxeHead>, Without<Player>),
-    >,
+fn change_player_color(
+    mut player_query: Query<(&mut Sprite, Option<&Invincibility>), With<Player>>,
 ) {
-    let (mut transform, mut rotation) = match axe_head_query.iter_mut().next() {
-        Some(transform) => transform,
-        None => return,
-    };
-
-    let radian = rotation.0 + AXE_HEAD_SPEED * time.delta_seconds();
-    rotation.0 = if radian >= 2. * PI { 0. } else { radian };
-
-    let x = rotation.0.cos() * 3.;
-    let y = rotation.0.sin() * 3.;
-    transform.translation = Vec3::new(x, y, 0.);
+    for (mut sprite, invincibility) in player_query.iter_mut() {
+        sprite.color =
+            if invincibility.is_some() { HIT_PLAYER_COLOR } else { HEALTHY_PLAYER_COLOR };
+    }
 }
 
-fn change_player_color(
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn enemies_damage_player(
+    mut commands: Commands,
+    hit_settings: Res<PlayerHitSettings>,
+    mut state: ResMut<State<MyStates>>,
+    mut run_stats: ResMut<RunStats>,
+    mut contacts: ResMut<PlayerContacts>,
     mut events: EventReader<CollisionEvent>,
-    mut player_query: Query<(Entity, &mut Sprite), With<Player>>,
+    mut player_query: Query<(
+        Entity,
+        &Player,
+        &Transform,
+        &mut Health,
+        &mut Velocity,
+        Option<&Invincibility>,
+    )>,
+    enemies_query: Query<(&EnemyStats, &Transform), With<Enemy>>,
+    enemy_projectiles_query: Query<(&EnemyProjectile, &Transform)>,
 ) {
-    let (entity, mut player_sprite) = match player_query.iter_mut().next() {
-        Some(value) => value,
-        None => return,
-    };
+    let (entity, player, transform, mut health, mut velocity, invincibility) =
+        match player_query.iter_mut().next() {
+            Some(value) => value,
+            None => return,
+        };
 
     for event in events.iter() {
-        if let CollisionEvent::Started(data1, data2) = event {
-            let a = data1.collision_shape_entity();
-            let b = data2.collision_shape_entity();
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        let hurts = |layers| is_enemy_layer(layers) || is_enemy_projectile_layer(layers);
+        let other = if is_player_layer(layers_1) && hurts(layers_2) {
+            entity_2
+        } else if is_player_layer(layers_2) && hurts(layers_1) {
+            entity_1
+        } else {
+            continue;
+        };
 
-            if a == entity || b == entity {
-                player_sprite.color = HIT_PLAYER_COLOR;
-                return;
-            }
+        if event.is_started() {
+            contacts.0.insert(other);
+        } else {
+            contacts.0.remove(&other);
         }
     }
 
-    player_sprite.color = HEALTHY_PLAYER_COLOR;
+    let damage_of = |entity: Entity| match enemies_query.get(entity) {
+        Ok((stats, transform)) => Some((stats.contact_damage, transform.translation.xy())),
+        Err(_) => enemy_projectiles_query
+            .get(entity)
+            .ok()
+            .map(|(projectile, transform)| (projectile.damage, transform.translation.xy())),
+    };
+
+    // Forget what was despawned while touching the player, like the killed enemies.
+    contacts.0.retain(|entity| damage_of(*entity).is_some());
+
+    // The enemies still touching the player hurt it again once it's no longer invincible.
+    let hit =
+        contacts.0.iter().filter_map(|entity| damage_of(*entity)).max_by_key(|(damage, _)| *damage);
+
+    let (damage, hit_position) = match hit {
+        Some(value) if invincibility.is_none() => value,
+        _ => return,
+    };
+
+    health.0 = health.0.saturating_sub(damage);
+
+    // Push the player away from what hit it.
+    let direction = (transform.translation.xy() - hit_position).normalize_or_zero();
+    velocity.linear = (direction * hit_settings.knockback_speed).extend(0.);
+
+    commands
+        .entity(entity)
+        .insert(Invincibility(Timer::from_seconds(hit_settings.invincibility_duration, false)))
+        .insert(Knockback(Timer::from_seconds(hit_settings.knockback_duration, false)));
+
+    if health.0 == 0 {
+        run_stats.level = player.level;
+        info!(
+            "Player died after {:.1}s at level {} with {} xp",
+            run_stats.elapsed, run_stats.level, run_stats.xp
+        );
+        // Dying must win over any other transition requested during this frame.
+        let _ = state.overwrite_set(MyStates::GameOver);
+    }
+}
+
+fn tick_player_hit_reaction(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut invincibility_query: Query<(Entity, &mut Invincibility, &mut Visibility)>,
+    mut knockback_query: Query<(Entity, &mut Knockback)>,
+) {
+    for (entity, mut invincibility, mut visibility) in invincibility_query.iter_mut() {
+        if invincibility.0.tick(time.delta()).finished() {
+            visibility.is_visible = true;
+            commands.entity(entity).remove::<Invincibility>();
+        } else {
+            let blinks = (invincibility.0.elapsed_secs() / PLAYER_BLINK_PERIOD) as u32;
+            visibility.is_visible = blinks % 2 == 1;
+        }
+    }
+
+    for (entity, mut knockback) in knockback_query.iter_mut() {
+        if knockback.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
+
+fn tick_run_stats(time: Res<Time>, mut run_stats: ResMut<RunStats>) {
+    run_stats.elapsed += time.delta_seconds();
+}
+
+/// Despawns everything that belongs to a run so that a new one can be started from scratch.
+#[allow(clippy::type_complexity)]
+fn cleanup_run(
+    mut commands: Commands,
+    query: Query<
+        Entity,
+        Or<(
+            With<Player>,
+            With<Enemy>,
+            With<Gem>,
+            With<Stuff>,
+            With<GridLine>,
+            With<Projectile>,
+            With<DamageNumber>,
+            With<LightningArc>,
+            With<BossHealthBar>,
+        )>,
+    >,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
 fn create_loot(
     mut commands: Commands,
     iconset_assets: Res<IconsetAssets>,
-    mut player_query: Query<(&mut Player, &Transform)>,
+    mut level_ups: EventReader<LevelUp>,
+    player_query: Query<&Transform, With<Player>>,
 ) {
-    let (mut player, transform) = match player_query.iter_mut().next() {
+    let transform = match player_query.iter().next() {
         Some(value) => value,
         None => return,
     };
 
     let mut rng = rand::thread_rng();
-    if !player.lvl1_stuff_generated && player.xp >= 30 {
-        player.lvl1_stuff_generated = true;
+    for level_up in level_ups.iter() {
+        if level_up.level != FISHING_ROD_LEVEL {
+            continue;
+        }
+
         let pos = random_in_radius(&mut rng, transform.translation, 5.);
         let pos = move_from_deadzone(pos, 3.).extend(95.);
 
@@ -282,56 +464,108 @@ fn create_loot(
     }
 }
 
-fn axe_head_kill_ennemies(
+fn drop_boss_chests(
     mut commands: Commands,
     iconset_assets: Res<IconsetAssets>,
-    mut events: EventReader<CollisionEvent>,
-    ennemies_query: Query<&Transform>,
+    mut kills: EventReader<EnemyKilled>,
 ) {
-    events
-        .iter()
-        .filter(|e| e.is_started())
-        .filter_map(|event| {
-            let (entity_1, entity_2) = event.rigid_body_entities();
-            let (layers_1, layers_2) = event.collision_layers();
-            if is_weapon_layer(layers_1) && is_enemy_layer(layers_2) {
-                Some(entity_2)
-            } else if is_weapon_layer(layers_2) && is_enemy_layer(layers_1) {
-                Some(entity_1)
-            } else {
-                None
-            }
-        })
-        .for_each(|enemy_entity| {
-            if let Ok(transform) = ennemies_query.get_component::<Transform>(enemy_entity) {
-                let pos = transform.translation.xy().extend(80.0);
-                commands.entity(enemy_entity).despawn();
-                commands
-                    .spawn_bundle(SpriteSheetBundle {
-                        transform: Transform::from_translation(pos).with_scale(Vec3::splat(0.015)),
-                        sprite: TextureAtlasSprite::new(474), // blue diamond
-                        texture_atlas: iconset_assets.iconset_fantasy_standalone.clone(),
-                        ..Default::default()
-                    })
-                    .insert(MoveToPlayer::default())
-                    .insert(RigidBody::Sensor)
-                    .insert(CollisionShape::Sphere { radius: 0.25 })
-                    .insert(
-                        CollisionLayers::none()
-                            .with_group(GameLayer::Gem)
-                            .with_mask(GameLayer::Player),
-                    )
-                    .insert(Gem);
-            }
-        });
+    for kill in kills.iter().filter(|kill| kill.boss) {
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                transform: Transform::from_translation(kill.position.extend(95.))
+                    .with_scale(Vec3::splat(0.04)),
+                sprite: TextureAtlasSprite::new(594), // brown bag
+                texture_atlas: iconset_assets.iconset_fantasy_standalone.clone(),
+                ..Default::default()
+            })
+            .insert(RigidBody::Sensor)
+            .insert(CollisionShape::Cuboid { half_extends: Vec3::splat(0.5), border_radius: None })
+            .insert(
+                CollisionLayers::none().with_group(GameLayer::Stuff).with_mask(GameLayer::Player),
+            )
+            .insert(Stuff::Chest);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn weapons_damage_enemies(
+    mut hits: EventReader<WeaponHit>,
+    mut damages: EventWriter<EnemyDamaged>,
+    mut kills: EventWriter<EnemyKilled>,
+    mut commands: Commands,
+    mut ennemies_query: Query<
+        (&EnemyKind, &Transform, &EnemyStats, &mut Health, &mut Velocity, Option<&Boss>),
+        With<Enemy>,
+    >,
+) {
+    for hit in hits.iter() {
+        let (kind, transform, stats, mut health, mut velocity, boss) =
+            match ennemies_query.get_mut(hit.enemy) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+        // An enemy can be hit by multiple weapons during the same frame.
+        if health.0 == 0 {
+            continue;
+        }
+
+        let damage = hit.damage.min(health.0);
+        health.0 -= damage;
+        velocity.linear += (hit.knockback * (1. - stats.knockback_resistance)).extend(0.);
+        damages.send(EnemyDamaged { position: transform.translation.xy(), damage, crit: hit.crit });
+
+        if health.0 == 0 {
+            commands.entity(hit.enemy).despawn();
+            kills.send(EnemyKilled {
+                kind: kind.clone(),
+                position: transform.translation.xy(),
+                xp: stats.xp,
+                boss: boss.is_some(),
+            });
+        }
+    }
+}
+
+fn drop_gems(
+    mut commands: Commands,
+    iconset_assets: Res<IconsetAssets>,
+    mut run_stats: ResMut<RunStats>,
+    mut kills: EventReader<EnemyKilled>,
+) {
+    for kill in kills.iter() {
+        run_stats.kills += 1;
+
+        // Tougher enemies drop bigger gems that are worth more xp.
+        let xp = kill.xp;
+        let scale = if xp > 1 { 0.025 } else { 0.015 };
+        let pos = kill.position.extend(80.0);
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                transform: Transform::from_translation(pos).with_scale(Vec3::splat(scale)),
+                sprite: TextureAtlasSprite::new(474), // blue diamond
+                texture_atlas: iconset_assets.iconset_fantasy_standalone.clone(),
+                ..Default::default()
+            })
+            .insert(MoveToPlayer::default())
+            .insert(RigidBody::Sensor)
+            .insert(CollisionShape::Sphere { radius: 0.25 })
+            .insert(CollisionLayers::none().with_group(GameLayer::Gem).with_mask(GameLayer::Player))
+            .insert(Gem(xp));
+    }
 }
 
 fn player_loot_gems(
     mut commands: Commands,
+    mut run_stats: ResMut<RunStats>,
     mut player_query: Query<&mut Player>,
+    gems_query: Query<&Gem>,
     mut events: EventReader<CollisionEvent>,
 ) {
-    let mut player = player_query.single_mut();
+    let mut player = match player_query.iter_mut().next() {
+        Some(player) => player,
+        None => return,
+    };
 
     events
         .iter()
@@ -348,14 +582,38 @@ fn player_loot_gems(
             }
         })
         .for_each(|gem_entity| {
-            player.xp += 1;
-            commands.entity(gem_entity).despawn();
+            if let Ok(Gem(xp)) = gems_query.get(gem_entity) {
+                player.xp += xp;
+                run_stats.xp += xp;
+                commands.entity(gem_entity).despawn();
+            }
         });
 }
 
+/// Converts the xp of the player into levels, keeping the excess xp for the next level.
+fn player_level_up(
+    xp_curve: Res<XpCurve>,
+    mut level_ups: EventWriter<LevelUp>,
+    mut player_query: Query<&mut Player>,
+) {
+    for mut player in player_query.iter_mut() {
+        loop {
+            let required = xp_curve.xp_to_next_level(player.level);
+            if player.xp < required {
+                break;
+            }
+
+            player.xp -= required;
+            player.level += 1;
+            level_ups.send(LevelUp { level: player.level });
+        }
+    }
+}
+
 fn player_loot_stuff(
     mut commands: Commands,
     mut loot_all_gems: ResMut<LootAllGemsFor>,
+    mut pending_upgrades: ResMut<PendingUpgrades>,
     mut events: EventReader<CollisionEvent>,
     stuff_query: Query<&Stuff>,
 ) {
@@ -374,8 +632,12 @@ fn player_loot_stuff(
             }
         })
         .for_each(|entity| {
-            if let Ok(Stuff::FishingRod) = stuff_query.get_component::<Stuff>(entity) {
-                *loot_all_gems = LootAllGemsFor(Timer::from_seconds(5., false));
+            match stuff_query.get_component::<Stuff>(entity) {
+                Ok(Stuff::FishingRod) => {
+                    *loot_all_gems = LootAllGemsFor(Timer::from_seconds(5., false));
+                }
+                Ok(Stuff::Chest) => pending_upgrades.0 += 1,
+                Err(_) => (),
             }
             commands.entity(entity).despawn();
         });
@@ -397,23 +659,31 @@ fn is_stuff_layer(layers: CollisionLayers) -> bool {
     layers.contains_group(GameLayer::Stuff)
 }
 
+fn is_enemy_projectile_layer(layers: CollisionLayers) -> bool {
+    layers.contains_group(GameLayer::EnemyProjectile)
+}
+
 fn is_gem_layer(layers: CollisionLayers) -> bool {
     layers.contains_group(GameLayer::Gem)
 }
 
+#[allow(clippy::type_complexity)]
 fn gems_player_attraction(
     time: Res<Time>,
     mut loot_all_gems: ResMut<LootAllGemsFor>,
-    player_query: Query<&Transform, With<Player>>,
+    player_query: Query<(&Transform, &PlayerStats), With<Player>>,
     mut gems_query: Query<(&mut Transform, &mut MoveToPlayer), (With<Gem>, Without<Player>)>,
 ) {
-    let player_transform = player_query.single();
+    let (player_transform, stats) = match player_query.iter().next() {
+        Some(value) => value,
+        None => return,
+    };
 
     loot_all_gems.0.tick(time.delta());
 
     for (mut transform, mut move_to_player) in gems_query.iter_mut() {
         let dist = player_transform.translation.xy().distance(transform.translation.xy());
-        if move_to_player.0 || !loot_all_gems.0.finished() || dist <= 2.0 {
+        if move_to_player.0 || !loot_all_gems.0.finished() || dist <= stats.magnet_radius {
             move_to_player.0 = true;
             let dir = (player_transform.translation.xy() - transform.translation.xy())
                 .normalize_or_zero();
@@ -437,33 +707,134 @@ fn camera_follow(
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum MyStates {
+pub enum MyStates {
     AssetLoading,
-    Next,
+    MainMenu,
+    Playing,
+    Paused,
+    UpgradeSelection,
+    GameOver,
 }
 
-#[derive(Default, Component)]
+/// Statistics about the current, or last, run.
+#[derive(Default)]
+pub struct RunStats {
+    pub elapsed: f32, // seconds
+    pub level: usize,
+    pub xp: usize,
+    pub kills: usize,
+}
+
+#[derive(Component)]
 pub struct Player {
-    lvl1_stuff_generated: bool,
+    level: usize,
+    /// The xp gathered since the last level up.
     xp: usize,
 }
 
-#[derive(Component)]
-pub struct Gem;
+impl Default for Player {
+    fn default() -> Player {
+        Player { level: 1, xp: 0 }
+    }
+}
 
+/// The stats of the player that can be improved with upgrades.
 #[derive(Component)]
-pub enum Stuff {
-    FishingRod,
+pub struct PlayerStats {
+    pub max_health: usize,
+    pub speed: f32,
+    pub magnet_radius: f32,
+}
+
+impl Default for PlayerStats {
+    fn default() -> PlayerStats {
+        PlayerStats {
+            max_health: PLAYER_HEALTH,
+            speed: PLAYER_SPEED,
+            magnet_radius: PLAYER_MAGNET_RADIUS,
+        }
+    }
+}
+
+/// Sent every time the player reaches a new level.
+pub struct LevelUp {
+    pub level: usize,
 }
 
+/// The amount of xp required to go from one level to the next,
+/// it grows linearly with the current level of the player.
+pub struct XpCurve {
+    /// The xp required to go from level 1 to level 2.
+    pub base: usize,
+    /// The xp added to the requirement at every level.
+    pub increment: usize,
+}
+
+impl XpCurve {
+    /// At least 1 xp is required, a level up for free would never end.
+    pub fn xp_to_next_level(&self, level: usize) -> usize {
+        (self.base + self.increment * level.saturating_sub(1)).max(1)
+    }
+}
+
+impl Default for XpCurve {
+    fn default() -> XpCurve {
+        XpCurve { base: 10, increment: 5 }
+    }
+}
+
+/// How the player reacts when an enemy hits it.
+pub struct PlayerHitSettings {
+    /// The time during which the player can't be hurt again, in seconds.
+    pub invincibility_duration: f32,
+    /// The time during which the player is pushed away and can't move, in seconds.
+    pub knockback_duration: f32,
+    /// The speed at which the player is pushed away from the enemy.
+    pub knockback_speed: f32,
+}
+
+impl Default for PlayerHitSettings {
+    fn default() -> PlayerHitSettings {
+        PlayerHitSettings {
+            invincibility_duration: 1.,
+            knockback_duration: 0.15,
+            knockback_speed: 20.,
+        }
+    }
+}
+
+/// The enemies and enemy projectiles touching the player.
+#[derive(Default)]
+pub struct PlayerContacts(HashSet<Entity>);
+
+/// The player can't be hurt while this component is attached.
 #[derive(Component)]
-pub struct AxeHead;
+pub struct Invincibility(Timer);
 
+/// The label of the system hurting the player, the player moves before it so the knockback wins.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerHit;
+
+/// The player is being pushed away and can't be moved while this component is attached.
 #[derive(Component)]
-pub struct LootAllGemsFor(Timer);
+pub struct Knockback(Timer);
+
+/// A gem worth this amount of xp.
+#[derive(Component)]
+pub struct Gem(pub usize);
+
+#[derive(Component)]
+pub struct GridLine;
 
 #[derive(Component)]
-pub struct RotationRadian(f32);
+pub enum Stuff {
+    FishingRod,
+    /// Dropped by the bosses, it gives an upgrade to the player.
+    Chest,
+}
+
+#[derive(Component)]
+pub struct LootAllGemsFor(Timer);
 
 #[derive(Default, Component)]
 pub struct MoveToPlayer(bool);
@@ -475,7 +846,11 @@ pub struct Health(pub usize);
 pub enum GameLayer {
     Player,
     Weapon,
+    Projectile,
     Enemies,
     Gem,
     Stuff,
+    EnemyProjectile,
+    /// The walls and other static bodies the enemies find their way around.
+    Obstacle,
 }
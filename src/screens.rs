@@ -0,0 +1,160 @@
+use bevy::prelude::*;
+use heron::PhysicsTime;
+
+use crate::assets::*;
+use crate::{MyStates, RunStats};
+
+pub const TITLE_FONT_SIZE: f32 = 80.;
+pub const TEXT_FONT_SIZE: f32 = 30.;
+
+#[derive(Component)]
+pub struct MainMenuScreen;
+
+#[derive(Component)]
+pub struct PauseScreen;
+
+#[derive(Component)]
+pub struct GameOverScreen;
+
+pub fn setup_main_menu(mut commands: Commands, font_assets: Res<FontAssets>) {
+    spawn_screen(
+        &mut commands,
+        &font_assets,
+        MainMenuScreen,
+        "Mass Survivor",
+        &["Press Enter to start"],
+    );
+}
+
+pub fn main_menu_input(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<MyStates>>) {
+    if keys.just_pressed(KeyCode::Return) {
+        keys.reset(KeyCode::Return);
+        let _ = state.set(MyStates::Playing);
+    }
+}
+
+pub fn pause_input(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<MyStates>>) {
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.reset(KeyCode::Escape);
+        let _ = state.push(MyStates::Paused);
+    }
+}
+
+pub fn setup_pause_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    physics_time.pause();
+    spawn_screen(
+        &mut commands,
+        &font_assets,
+        PauseScreen,
+        "Paused",
+        &["Press Escape to resume", "Press Q to quit to the main menu"],
+    );
+}
+
+pub fn pause_menu_input(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<MyStates>>) {
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.reset(KeyCode::Escape);
+        let _ = state.pop();
+    } else if keys.just_pressed(KeyCode::Q) {
+        keys.reset(KeyCode::Q);
+        let _ = state.replace(MyStates::MainMenu);
+    }
+}
+
+pub fn exit_pause_menu(
+    commands: Commands,
+    screen_query: Query<Entity, With<PauseScreen>>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    physics_time.resume();
+    despawn_screen(commands, screen_query);
+}
+
+pub fn setup_game_over(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    run_stats: Res<RunStats>,
+) {
+    let survived = format!(
+        "You survived {:.1}s, reached level {}, killed {} enemies and gathered {} xp",
+        run_stats.elapsed, run_stats.level, run_stats.kills, run_stats.xp
+    );
+    spawn_screen(
+        &mut commands,
+        &font_assets,
+        GameOverScreen,
+        "Game Over",
+        &[
+            survived.as_str(),
+            "Press Enter to play again",
+            "Press Escape to go back to the main menu",
+        ],
+    );
+}
+
+pub fn game_over_input(mut keys: ResMut<Input<KeyCode>>, mut state: ResMut<State<MyStates>>) {
+    if keys.just_pressed(KeyCode::Return) {
+        keys.reset(KeyCode::Return);
+        let _ = state.set(MyStates::Playing);
+    } else if keys.just_pressed(KeyCode::Escape) {
+        keys.reset(KeyCode::Escape);
+        let _ = state.set(MyStates::MainMenu);
+    }
+}
+
+/// Despawns every entity of a screen, to be used in the `on_exit` system set of its state.
+pub fn despawn_screen<T: Component>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn spawn_screen<T: Component>(
+    commands: &mut Commands,
+    font_assets: &FontAssets,
+    marker: T,
+    title: &str,
+    lines: &[&str],
+) {
+    commands.spawn_bundle(screen_node()).insert(marker).with_children(|parent| {
+        parent.spawn_bundle(screen_text(font_assets, title, TITLE_FONT_SIZE));
+        for line in lines {
+            parent.spawn_bundle(screen_text(font_assets, line, TEXT_FONT_SIZE));
+        }
+    });
+}
+
+/// A full screen, translucent, node that centers its children in a column.
+pub fn screen_node() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        color: Color::rgba(0., 0., 0., 0.5).into(),
+        ..Default::default()
+    }
+}
+
+pub fn screen_text(font_assets: &FontAssets, value: &str, font_size: f32) -> TextBundle {
+    TextBundle {
+        style: Style { margin: Rect::all(Val::Px(10.)), ..Default::default() },
+        text: Text::with_section(
+            value,
+            TextStyle {
+                font: font_assets.dejavu_sans_bold.clone(),
+                font_size,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,164 @@
+use std::cmp::Ordering;
+
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+use crate::enemies::Enemy;
+
+const ENEMY_GRID_CELL_SIZE: f32 = 2.;
+
+/// The positions of the enemies bucketed by cell, rebuilt every frame,
+/// to find the enemies close to a point without iterating all of them.
+#[derive(Default)]
+pub struct EnemyGrid {
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec2)>>,
+    len: usize,
+}
+
+impl EnemyGrid {
+    fn cell(position: Vec2) -> (i32, i32) {
+        let cell = (position / ENEMY_GRID_CELL_SIZE).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+
+    pub fn clear(&mut self) {
+        // Keep the allocations of the cells, most of them are filled again on the next frame.
+        for enemies in self.cells.values_mut() {
+            enemies.clear();
+        }
+        self.len = 0;
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2) {
+        self.cells.entry(Self::cell(position)).or_default().push((entity, position));
+        self.len += 1;
+    }
+
+    /// Returns the enemies of the cells between the `min` and `max` cells, inclusive.
+    fn cells_between(
+        &self,
+        (min_x, min_y): (i32, i32),
+        (max_x, max_y): (i32, i32),
+    ) -> Box<dyn Iterator<Item = (Entity, Vec2)> + '_> {
+        // Infinite or huge rectangles reach the bounds of i32, the area can't fit in an i64.
+        let area =
+            (max_x as i64 - min_x as i64 + 1).saturating_mul(max_y as i64 - min_y as i64 + 1);
+        if area > self.cells.len() as i64 {
+            // Large areas, like the whole screen, are faster to go through by filtering the cells.
+            Box::new(
+                self.cells
+                    .iter()
+                    .filter(move |((x, y), _)| {
+                        (min_x..=max_x).contains(x) && (min_y..=max_y).contains(y)
+                    })
+                    .flat_map(|(_, enemies)| enemies.iter().copied()),
+            )
+        } else {
+            Box::new(
+                (min_x..=max_x)
+                    .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+                    .filter_map(move |cell| self.cells.get(&cell))
+                    .flatten()
+                    .copied(),
+            )
+        }
+    }
+
+    /// Returns the cells at exactly `ring` cells from the `center` cell.
+    fn ring_cells((x, y): (i32, i32), ring: i32) -> Vec<(i32, i32)> {
+        if ring == 0 {
+            return vec![(x, y)];
+        }
+
+        let mut cells = Vec::with_capacity(8 * ring as usize);
+        for i in -ring..ring {
+            cells.push((x + i, y - ring));
+            cells.push((x + ring, y + i));
+            cells.push((x - i, y + ring));
+            cells.push((x - ring, y - i));
+        }
+        cells
+    }
+
+    /// Returns the enemies within `radius` of `point`.
+    pub fn within_radius(
+        &self,
+        point: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        self.in_rect(point - Vec2::splat(radius), point + Vec2::splat(radius))
+            .filter(move |(_, p)| p.distance_squared(point) <= radius * radius)
+    }
+
+    /// Returns the enemies inside of the rectangle going from `min` to `max`.
+    pub fn in_rect(&self, min: Vec2, max: Vec2) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        self.cells_between(Self::cell(min), Self::cell(max))
+            .filter(move |(_, p)| p.cmpge(min).all() && p.cmple(max).all())
+    }
+
+    /// Returns the closest enemy within `radius` of `point` that isn't filtered out.
+    pub fn nearest(
+        &self,
+        point: Vec2,
+        radius: f32,
+        filter: impl Fn(Entity) -> bool,
+    ) -> Option<(Entity, Vec2)> {
+        self.within_radius(point, radius).filter(|(entity, _)| filter(*entity)).min_by(
+            |(_, a), (_, b)| {
+                let a = a.distance_squared(point);
+                let b = b.distance_squared(point);
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            },
+        )
+    }
+
+    /// Returns the `k` enemies closest to `point`, sorted by distance, whatever their distance.
+    ///
+    /// The cells are searched ring by ring around the cell of the point,
+    /// until the `k` closest enemies found are closer than any unsearched cell.
+    pub fn nearest_k(&self, point: Vec2, k: usize) -> Vec<(Entity, Vec2)> {
+        let k = k.min(self.len);
+        if k == 0 {
+            return Vec::new();
+        }
+
+        let center = Self::cell(point);
+        let mut found = Vec::new();
+        let mut ring = 0;
+        loop {
+            for cell in Self::ring_cells(center, ring) {
+                if let Some(enemies) = self.cells.get(&cell) {
+                    found.extend_from_slice(enemies);
+                }
+            }
+            found.sort_by(|(_, a): &(Entity, Vec2), (_, b)| {
+                let a = a.distance_squared(point);
+                let b = b.distance_squared(point);
+                a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+            });
+
+            // Every enemy outside of the searched rings is at least this far from the point.
+            let searched = ring as f32 * ENEMY_GRID_CELL_SIZE;
+            if found.len() == self.len
+                || (found.len() >= k && found[k - 1].1.distance(point) <= searched)
+            {
+                break;
+            }
+            ring += 1;
+        }
+
+        found.truncate(k);
+        found
+    }
+}
+
+pub fn update_enemy_grid(
+    mut grid: ResMut<EnemyGrid>,
+    enemies_query: Query<(Entity, &Transform), With<Enemy>>,
+) {
+    grid.clear();
+    for (entity, transform) in enemies_query.iter() {
+        grid.insert(entity, transform.translation.xy());
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+
+use crate::enemies::Enemy;
+use crate::weapons::WeaponHit;
+
+const STATUS_EFFECT_TICK: f32 = 0.5; // seconds
+const MAX_POISON_STACKS: usize = 5;
+
+const BURN_COLOR: Color = Color::rgb(1., 0.55, 0.2);
+const POISON_COLOR: Color = Color::rgb(0.5, 1., 0.4);
+const FREEZE_COLOR: Color = Color::rgb(0.5, 0.8, 1.);
+const SLOW_COLOR: Color = Color::rgb(0.7, 0.7, 1.);
+
+/// The label of the system tinting the enemies, the systems flashing them run after it.
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StatusEffectsTint;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusEffectKind {
+    /// Deals damage over time, reapplying it refreshes its duration.
+    Burn,
+    /// Deals damage over time, reapplying it adds a stack and refreshes its duration.
+    Poison,
+    /// Stops the movement of the enemy, reapplying it refreshes its duration.
+    Freeze,
+    /// Reduces the speed of the enemy, reapplying it keeps the strongest slow.
+    Slow,
+}
+
+/// An effect applied by a weapon when it hits an enemy.
+#[derive(Debug, Clone, Copy)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub duration: f32, // seconds
+    /// The damage dealt at every tick for burn and poison,
+    /// the fraction of speed removed, between 0 and 1, for slow.
+    pub potency: f32,
+}
+
+struct ActiveStatusEffect {
+    effect: StatusEffect,
+    stacks: usize,
+    remaining: Timer,
+    tick: Timer,
+}
+
+/// The status effects currently affecting an enemy.
+#[derive(Default, Component)]
+pub struct StatusEffects(Vec<ActiveStatusEffect>);
+
+impl StatusEffects {
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let active = match self.0.iter_mut().find(|a| a.effect.kind == effect.kind) {
+            Some(active) => active,
+            None => {
+                self.0.push(ActiveStatusEffect {
+                    effect,
+                    stacks: 1,
+                    remaining: Timer::from_seconds(effect.duration, false),
+                    tick: Timer::from_seconds(STATUS_EFFECT_TICK, true),
+                });
+                return;
+            }
+        };
+
+        match effect.kind {
+            StatusEffectKind::Poison => {
+                active.stacks = (active.stacks + 1).min(MAX_POISON_STACKS);
+            }
+            StatusEffectKind::Burn | StatusEffectKind::Freeze | StatusEffectKind::Slow => {
+                active.effect.potency = active.effect.potency.max(effect.potency);
+            }
+        }
+
+        let duration = active.remaining.duration().as_secs_f32().max(effect.duration);
+        active.remaining = Timer::from_seconds(duration, false);
+    }
+
+    /// The factor to apply to the movement of the enemy.
+    pub fn speed_multiplier(&self) -> f32 {
+        self.0.iter().fold(1., |multiplier, active| match active.effect.kind {
+            StatusEffectKind::Freeze => 0.,
+            StatusEffectKind::Slow => multiplier * (1. - active.effect.potency.clamp(0., 1.)),
+            StatusEffectKind::Burn | StatusEffectKind::Poison => multiplier,
+        })
+    }
+
+    pub fn tint(&self) -> Color {
+        let has = |kind: StatusEffectKind| self.0.iter().any(|a| a.effect.kind == kind);
+        if has(StatusEffectKind::Freeze) {
+            FREEZE_COLOR
+        } else if has(StatusEffectKind::Burn) {
+            BURN_COLOR
+        } else if has(StatusEffectKind::Poison) {
+            POISON_COLOR
+        } else if has(StatusEffectKind::Slow) {
+            SLOW_COLOR
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+pub fn apply_status_effects(
+    mut hits: EventReader<WeaponHit>,
+    mut enemies_query: Query<&mut StatusEffects, With<Enemy>>,
+) {
+    for hit in hits.iter() {
+        if let Some(effect) = hit.effect {
+            if let Ok(mut effects) = enemies_query.get_mut(hit.enemy) {
+                effects.apply(effect);
+            }
+        }
+    }
+}
+
+/// Deals the damage over time of the status effects, removes the expired ones,
+/// holds the frozen enemies in place and tints the enemies according to the effects they suffer from.
+pub fn tick_status_effects(
+    time: Res<Time>,
+    mut hits: EventWriter<WeaponHit>,
+    mut enemies_query: Query<
+        (Entity, &mut StatusEffects, &mut TextureAtlasSprite, &mut Velocity),
+        With<Enemy>,
+    >,
+) {
+    for (entity, mut effects, mut sprite, mut velocity) in enemies_query.iter_mut() {
+        if effects.0.is_empty() {
+            continue;
+        }
+
+        for active in effects.0.iter_mut() {
+            active.remaining.tick(time.delta());
+            let ticks = active.tick.tick(time.delta()).times_finished();
+            let damage = match active.effect.kind {
+                StatusEffectKind::Burn | StatusEffectKind::Poison => {
+                    (active.effect.potency * active.stacks as f32).round() as usize
+                }
+                StatusEffectKind::Freeze | StatusEffectKind::Slow => 0,
+            };
+
+            for _ in 0..ticks {
+                if damage > 0 {
+                    hits.send(WeaponHit {
+                        enemy: entity,
+                        damage,
+                        crit: false,
+                        knockback: Vec2::ZERO,
+                        effect: None,
+                    });
+                }
+            }
+        }
+
+        effects.0.retain(|active| !active.remaining.finished());
+        if effects.speed_multiplier() == 0. {
+            velocity.linear = Vec3::ZERO;
+        }
+        sprite.color = effects.tint();
+    }
+}
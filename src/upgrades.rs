@@ -0,0 +1,204 @@
+use bevy::prelude::*;
+use heron::PhysicsTime;
+use rand::seq::SliceRandom;
+
+use crate::assets::*;
+use crate::screens::*;
+use crate::weapons::*;
+use crate::{Health, LevelUp, MyStates, PlayerStats};
+
+const UPGRADE_CHOICES: usize = 3;
+/// The number of weapons the player can wield at once, a new weapon replaces an old one past it.
+const MAX_WEAPONS: usize = 3;
+const UPGRADE_KEYS: [KeyCode; UPGRADE_CHOICES] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Upgrade {
+    NewWeapon(WeaponKind),
+    /// Replaces the first weapon with a new weapon of the second kind.
+    ReplaceWeapon(WeaponKind, WeaponKind),
+    WeaponLevel(WeaponKind),
+    MaxHealth,
+    MoveSpeed,
+    Magnet,
+}
+
+impl Upgrade {
+    /// Returns the upgrades that can be proposed to a player wielding these weapons.
+    fn available<'a>(weapons: impl IntoIterator<Item = &'a Weapon>) -> Vec<Upgrade> {
+        let weapons: Vec<_> = weapons.into_iter().collect();
+        let mut upgrades = vec![Upgrade::MaxHealth, Upgrade::MoveSpeed, Upgrade::Magnet];
+
+        // Once all the slots are taken, the weakest weapon makes room for the new one.
+        let weakest = weapons.iter().min_by_key(|w| w.level).map(|w| w.kind);
+        for kind in WeaponKind::ALL {
+            match weapons.iter().find(|w| w.kind == kind) {
+                Some(weapon) if !weapon.is_max_level() => upgrades.push(Upgrade::WeaponLevel(kind)),
+                Some(_) => (),
+                None => match weakest {
+                    Some(old) if weapons.len() >= MAX_WEAPONS => {
+                        upgrades.push(Upgrade::ReplaceWeapon(old, kind))
+                    }
+                    _ => upgrades.push(Upgrade::NewWeapon(kind)),
+                },
+            }
+        }
+
+        upgrades
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Upgrade::NewWeapon(kind) => format!("New weapon: {}", kind.name()),
+            Upgrade::ReplaceWeapon(old, new) => {
+                format!("New weapon: {}, replaces {}", new.name(), old.name())
+            }
+            Upgrade::WeaponLevel(kind) => format!("{} level up", kind.name()),
+            Upgrade::MaxHealth => "Max health +20".to_string(),
+            Upgrade::MoveSpeed => "Move speed +10%".to_string(),
+            Upgrade::Magnet => "Gem attraction radius +1".to_string(),
+        }
+    }
+}
+
+/// The number of level ups for which the player didn't pick an upgrade yet.
+#[derive(Default)]
+pub struct PendingUpgrades(pub usize);
+
+/// The upgrades proposed on the current upgrade selection screen.
+#[derive(Default)]
+pub struct UpgradeChoices(pub Vec<Upgrade>);
+
+#[derive(Component)]
+pub struct UpgradeScreen;
+
+/// A button that selects the upgrade at this index in the `UpgradeChoices`.
+#[derive(Component)]
+pub struct UpgradeButton(usize);
+
+pub fn queue_upgrade_selection(
+    mut level_ups: EventReader<LevelUp>,
+    mut pending: ResMut<PendingUpgrades>,
+    mut state: ResMut<State<MyStates>>,
+) {
+    pending.0 += level_ups.iter().count();
+    if pending.0 > 0 {
+        let _ = state.push(MyStates::UpgradeSelection);
+    }
+}
+
+pub fn setup_upgrade_selection(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    mut physics_time: ResMut<PhysicsTime>,
+    weapon_query: Query<&Weapon>,
+) {
+    physics_time.pause();
+
+    let mut rng = rand::thread_rng();
+    let upgrades = Upgrade::available(weapon_query.iter());
+    let choices: Vec<_> = upgrades.choose_multiple(&mut rng, UPGRADE_CHOICES).copied().collect();
+
+    commands.spawn_bundle(screen_node()).insert(UpgradeScreen).with_children(|parent| {
+        parent.spawn_bundle(screen_text(&font_assets, "Level up!", TITLE_FONT_SIZE));
+        for (i, upgrade) in choices.iter().enumerate() {
+            let label = format!("{}. {}", i + 1, upgrade.description());
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(600.), Val::Px(70.)),
+                        margin: Rect::all(Val::Px(10.)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: BUTTON_COLOR.into(),
+                    ..Default::default()
+                })
+                .insert(UpgradeButton(i))
+                .with_children(|parent| {
+                    parent.spawn_bundle(screen_text(&font_assets, &label, TEXT_FONT_SIZE));
+                });
+        }
+    });
+
+    commands.insert_resource(UpgradeChoices(choices));
+}
+
+/// Lets the player pick an upgrade with the number keys or by clicking on it.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn upgrade_selection_input(
+    mut commands: Commands,
+    mut keys: ResMut<Input<KeyCode>>,
+    choices: Res<UpgradeChoices>,
+    mut pending: ResMut<PendingUpgrades>,
+    mut state: ResMut<State<MyStates>>,
+    mut interaction_query: Query<
+        (&Interaction, &UpgradeButton, &mut UiColor),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut player_query: Query<(Entity, &mut PlayerStats, &mut Health)>,
+    mut weapon_query: Query<(Entity, &mut Weapon)>,
+    weapon_textures: Res<WeaponTextures>,
+) {
+    let mut selected = None;
+    for (i, key) in UPGRADE_KEYS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            keys.reset(*key);
+            selected = Some(i);
+        }
+    }
+
+    for (interaction, button, mut color) in interaction_query.iter_mut() {
+        match interaction {
+            Interaction::Clicked => selected = Some(button.0),
+            Interaction::Hovered => *color = HOVERED_BUTTON_COLOR.into(),
+            Interaction::None => *color = BUTTON_COLOR.into(),
+        }
+    }
+
+    let upgrade = match selected.and_then(|i| choices.0.get(i)) {
+        Some(upgrade) => *upgrade,
+        None => return,
+    };
+
+    for (player, mut stats, mut health) in player_query.iter_mut() {
+        match upgrade {
+            Upgrade::NewWeapon(kind) => {
+                add_weapon(&mut commands, &weapon_textures, player, kind);
+            }
+            Upgrade::ReplaceWeapon(old, new) => {
+                for (weapon, _) in weapon_query.iter().filter(|(_, w)| w.kind == old) {
+                    remove_weapon(&mut commands, player, weapon);
+                }
+                add_weapon(&mut commands, &weapon_textures, player, new);
+            }
+            Upgrade::WeaponLevel(kind) => {
+                for (_, mut weapon) in weapon_query.iter_mut().filter(|(_, w)| w.kind == kind) {
+                    weapon.level_up();
+                }
+            }
+            Upgrade::MaxHealth => {
+                stats.max_health += 20;
+                health.0 += 20;
+            }
+            Upgrade::MoveSpeed => stats.speed *= 1.1,
+            Upgrade::Magnet => stats.magnet_radius += 1.,
+        }
+    }
+
+    pending.0 = pending.0.saturating_sub(1);
+    let _ = state.pop();
+}
+
+pub fn exit_upgrade_selection(
+    commands: Commands,
+    screen_query: Query<Entity, With<UpgradeScreen>>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    physics_time.resume();
+    despawn_screen(commands, screen_query);
+}
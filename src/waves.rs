@@ -0,0 +1,462 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::assets::DataAssets;
+use crate::enemies::{EnemyKind, MovementKind};
+use crate::enemy_definitions::EnemyDefinitions;
+
+/// How the enemies of a group are placed around the origin of the group.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum SpawnPattern {
+    /// The enemies spawn anywhere in a disk around the origin.
+    Cluster { radius: f32 },
+    /// The enemies spawn in a disk around the origin, pushed away from the axes of the map.
+    Scattered { radius: f32, deadzone: f32 },
+}
+
+/// Makes the enemies of a wave bosses, summoning minions of the given kind.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BossDefinition {
+    pub minions: EnemyKind,
+    pub minion_count: usize,
+}
+
+impl BossDefinition {
+    /// The wave spawned around a boss when it summons its minions.
+    pub fn minion_wave(&self) -> WaveDefinition {
+        WaveDefinition {
+            time: 0.,
+            repeat: None,
+            kind: self.minions.clone(),
+            groups: 1,
+            group_size: self.minion_count,
+            movement: MovementKind::Tracking,
+            pattern: SpawnPattern::Cluster { radius: 3. },
+            boss: None,
+        }
+    }
+}
+
+/// An entry of the wave timeline, spawning groups of enemies of the same kind.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaveDefinition {
+    /// The time of the first spawn, in seconds since the start of the run.
+    pub time: f32,
+    /// The time between two spawns of this wave, in seconds, it only spawns once when absent.
+    #[serde(default)]
+    pub repeat: Option<f32>,
+    pub kind: EnemyKind,
+    pub groups: usize,
+    pub group_size: usize,
+    pub movement: MovementKind,
+    pub pattern: SpawnPattern,
+    #[serde(default)]
+    pub boss: Option<BossDefinition>,
+}
+
+impl WaveDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if !self.time.is_finite() || self.time < 0. {
+            return Err(format!(
+                "the time must be a positive number of seconds, got {}",
+                self.time
+            ));
+        }
+        if let Some(repeat) = self.repeat {
+            if !repeat.is_finite() || repeat <= 0. {
+                return Err(format!(
+                    "the repeat delay must be more than 0 seconds, got {}",
+                    repeat
+                ));
+            }
+        }
+        if self.groups == 0 {
+            return Err("there must be at least one group".to_string());
+        }
+        if self.group_size == 0 {
+            return Err("the group size must be at least 1".to_string());
+        }
+        if let Some(BossDefinition { minion_count: 0, .. }) = self.boss {
+            return Err("a boss must summon at least one minion".to_string());
+        }
+        validate_movement(self.movement)?;
+        match self.pattern {
+            SpawnPattern::Cluster { radius } | SpawnPattern::Scattered { radius, .. }
+                if !radius.is_finite() || radius <= 0. =>
+            {
+                Err(format!("the spawn pattern radius must be more than 0, got {}", radius))
+            }
+            SpawnPattern::Scattered { deadzone, .. } if !deadzone.is_finite() || deadzone < 0. => {
+                Err(format!("the spawn pattern deadzone can't be negative, got {}", deadzone))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the number of times this wave spawns between `from`, inclusive, and `to`, exclusive,
+    /// so a wave at 0s spawns on the first frame of the run.
+    pub fn spawns_between(&self, from: f32, to: f32) -> usize {
+        let spawns_until = |t: f32| match self.repeat {
+            _ if t <= self.time => 0,
+            Some(repeat) => ((t - self.time) / repeat).ceil() as usize,
+            None => 1,
+        };
+        spawns_until(to) - spawns_until(from)
+    }
+}
+
+fn validate_movement(movement: MovementKind) -> Result<(), String> {
+    match movement {
+        MovementKind::Encircle { radius, .. } if !radius.is_finite() || radius <= 0. => {
+            Err(format!("the ring radius must be more than 0, got {}", radius))
+        }
+        MovementKind::Encircle { shrink, .. } if !shrink.is_finite() || shrink < 0. => {
+            Err(format!("the ring shrink rate can't be negative, got {}", shrink))
+        }
+        MovementKind::Flocking { separation, alignment, cohesion }
+            if [separation, alignment, cohesion].iter().any(|w| !w.is_finite() || *w < 0.) =>
+        {
+            Err("the flocking weights can't be negative".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A value growing linearly with the time elapsed since the start of the run.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct DifficultyCurve {
+    pub base: f32,
+    pub per_minute: f32,
+    #[serde(default)]
+    pub max: Option<f32>,
+}
+
+impl DifficultyCurve {
+    /// Checks the curve never gives a negative or non-finite value.
+    fn validate(&self, name: &str) -> Result<(), String> {
+        if !self.base.is_finite() || self.base < 0. {
+            return Err(format!("the {} base can't be negative, got {}", name, self.base));
+        }
+        if !self.per_minute.is_finite() || self.per_minute < 0. {
+            return Err(format!(
+                "the {} growth per minute can't be negative, got {}",
+                name, self.per_minute
+            ));
+        }
+        match self.max {
+            Some(max) if !max.is_finite() || max < self.base => Err(format!(
+                "the {} max can't be lower than its base {}, got {}",
+                name, self.base, max
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn at(&self, elapsed: f32) -> f32 {
+        let value = self.base + self.per_minute * elapsed / 60.;
+        self.max.map_or(value, |max| value.min(max))
+    }
+}
+
+/// The modifiers applied to the enemies of a wave.
+#[derive(Debug, Clone, Copy)]
+pub struct Difficulty {
+    pub health: f32,
+    pub speed: f32,
+    /// The chance for each enemy to be an elite, between 0 and 1.
+    pub elite_chance: f32,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty { health: 1., speed: 1., elite_chance: 0. }
+    }
+}
+
+/// The procedural waves spawned once the last wave of the timeline started.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EndlessMode {
+    /// The time between two procedural waves, in seconds.
+    pub interval: f32,
+    /// The enemy kinds picked at random for each wave.
+    pub kinds: Vec<EnemyKind>,
+    pub movements: Vec<MovementKind>,
+    pub groups: usize,
+    pub group_size: DifficultyCurve,
+    /// The factor applied to the health of the enemies.
+    pub health: DifficultyCurve,
+    /// The factor applied to the speed of the enemies.
+    pub speed: DifficultyCurve,
+    pub elite_chance: DifficultyCurve,
+}
+
+impl EndlessMode {
+    fn validate(&self) -> Result<(), String> {
+        if !self.interval.is_finite() || self.interval <= 0. {
+            return Err(format!("the interval must be more than 0 seconds, got {}", self.interval));
+        }
+        if self.kinds.is_empty() {
+            return Err("there must be at least one enemy kind".to_string());
+        }
+        if self.movements.is_empty() {
+            return Err("there must be at least one movement kind".to_string());
+        }
+        if self.groups == 0 {
+            return Err("there must be at least one group".to_string());
+        }
+        self.group_size.validate("group size")?;
+        self.health.validate("health")?;
+        self.speed.validate("speed")?;
+        self.elite_chance.validate("elite chance")?;
+        if self.health.base == 0. || self.speed.base == 0. {
+            return Err("the health and speed must start above 0".to_string());
+        }
+        self.movements.iter().try_for_each(|movement| validate_movement(*movement))
+    }
+
+    /// Returns the waves generated between `from`, exclusive, and `to`, inclusive,
+    /// for an endless mode starting at `start`.
+    pub fn waves_between<R: Rng>(
+        &self,
+        rng: &mut R,
+        start: f32,
+        from: f32,
+        to: f32,
+    ) -> Vec<(WaveDefinition, Difficulty)> {
+        let waves_until = |t: f32| ((t - start) / self.interval).max(0.) as usize;
+        (waves_until(from)..waves_until(to))
+            .filter_map(|i| {
+                let time = start + self.interval * (i + 1) as f32;
+                let kind = self.kinds.choose(rng)?.clone();
+                let movement = *self.movements.choose(rng)?;
+                let pattern = match movement {
+                    MovementKind::Tracking => SpawnPattern::Cluster { radius: 3. },
+                    MovementKind::SlowWalking => {
+                        SpawnPattern::Scattered { radius: 10., deadzone: 3. }
+                    }
+                    MovementKind::RunningGroup => SpawnPattern::Cluster { radius: 10. },
+                    MovementKind::Ranged => SpawnPattern::Scattered { radius: 10., deadzone: 3. },
+                    MovementKind::Charging => SpawnPattern::Scattered { radius: 10., deadzone: 3. },
+                    MovementKind::Flocking { .. } => SpawnPattern::Cluster { radius: 4. },
+                    // The rings ignore the spawn pattern.
+                    MovementKind::Encircle { radius, .. } => SpawnPattern::Cluster { radius },
+                };
+                let wave = WaveDefinition {
+                    time,
+                    repeat: None,
+                    kind,
+                    groups: self.groups,
+                    group_size: self.group_size.at(time).round().max(1.) as usize,
+                    movement,
+                    pattern,
+                    boss: None,
+                };
+                let difficulty = Difficulty {
+                    health: self.health.at(time),
+                    speed: self.speed.at(time),
+                    elite_chance: self.elite_chance.at(time).clamp(0., 1.),
+                };
+                Some((wave, difficulty))
+            })
+            .collect()
+    }
+}
+
+/// The waves of enemies of a run, loaded from a `.waves.ron` file.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "8a7c5fce-e5ea-4494-97ad-82cedcc5eeac"]
+pub struct WaveTimeline {
+    pub waves: Vec<WaveDefinition>,
+    /// Keeps spawning waves after the timeline when present.
+    #[serde(default)]
+    pub endless: Option<EndlessMode>,
+}
+
+impl WaveTimeline {
+    /// The time of the first spawn of the last wave, when the endless mode starts.
+    pub fn end(&self) -> f32 {
+        self.waves.iter().map(|w| w.time).fold(0., f32::max)
+    }
+
+    /// Logs an error for every wave spawning an enemy kind that has no definition.
+    pub fn check_kinds(&self, definitions: &EnemyDefinitions) {
+        for (i, wave) in self.waves.iter().enumerate() {
+            if definitions.get(&wave.kind).is_none() {
+                error!(
+                    "Wave #{} at {}s spawns the enemy kind {:?} which has no definition",
+                    i + 1,
+                    wave.time,
+                    wave.kind.0
+                );
+            }
+            if let Some(boss) = wave.boss.as_ref().filter(|b| definitions.get(&b.minions).is_none())
+            {
+                error!(
+                    "The boss of wave #{} at {}s summons the enemy kind {:?} which has no definition",
+                    i + 1,
+                    wave.time,
+                    boss.minions.0
+                );
+            }
+        }
+
+        let endless_kinds = self.endless.iter().flat_map(|endless| endless.kinds.iter());
+        for kind in endless_kinds.filter(|kind| definitions.get(kind).is_none()) {
+            error!("The endless mode spawns the enemy kind {:?} which has no definition", kind.0);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WaveTimelineLoader;
+
+impl AssetLoader for WaveTimelineLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut timeline: WaveTimeline = ron::de::from_bytes(bytes)?;
+
+            // A bad wave is skipped instead of failing the whole timeline.
+            let path = load_context.path().display().to_string();
+            let mut index = 0;
+            timeline.waves.retain(|wave| {
+                index += 1;
+                match wave.validate() {
+                    Ok(()) => true,
+                    Err(reason) => {
+                        error!("Ignoring wave #{} of {}: {}", index, path, reason);
+                        false
+                    }
+                }
+            });
+
+            if let Some(Err(reason)) = timeline.endless.as_ref().map(|e| e.validate()) {
+                error!("Ignoring the endless mode of {}: {}", path, reason);
+                timeline.endless = None;
+            }
+
+            load_context.set_default_asset(LoadedAsset::new(timeline));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+/// Reports the enemy kinds without definition of a modified wave timeline,
+/// the spawner reads the timeline every frame so the changes apply to the next spawns.
+pub fn reload_wave_timeline(
+    mut events: EventReader<AssetEvent<WaveTimeline>>,
+    data_assets: Option<Res<DataAssets>>,
+    enemy_definitions: Res<Assets<EnemyDefinitions>>,
+    wave_timelines: Res<Assets<WaveTimeline>>,
+) {
+    let data_assets = match data_assets {
+        Some(data_assets) => data_assets,
+        None => return,
+    };
+
+    for event in events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if let (Some(definitions), Some(timeline)) =
+                (enemy_definitions.get(&data_assets.enemies), wave_timelines.get(handle))
+            {
+                info!("Reloaded {} waves", timeline.waves.len());
+                timeline.check_kinds(definitions);
+            }
+        }
+    }
+}
+
+/// Sent to spawn a wave around a position, instead of around the player.
+pub struct SummonWave {
+    pub wave: WaveDefinition,
+    pub position: Vec2,
+}
+
+/// The time elapsed since the start of the run, as seen by the wave spawner.
+#[derive(Default)]
+pub struct WaveClock(pub f32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 1. / 60.;
+
+    fn wave(time: f32, repeat: Option<f32>) -> WaveDefinition {
+        WaveDefinition {
+            time,
+            repeat,
+            kind: EnemyKind("BlueFish".to_string()),
+            groups: 1,
+            group_size: 1,
+            movement: MovementKind::Tracking,
+            pattern: SpawnPattern::Cluster { radius: 1. },
+            boss: None,
+        }
+    }
+
+    /// Returns the number of spawns of each frame of a run lasting `duration` seconds.
+    fn spawns_per_frame(wave: &WaveDefinition, duration: f32) -> Vec<usize> {
+        let frames = (duration / FRAME) as usize;
+        (0..frames).map(|i| wave.spawns_between(i as f32 * FRAME, (i + 1) as f32 * FRAME)).collect()
+    }
+
+    #[test]
+    fn wave_at_zero_spawns_on_the_first_frame() {
+        let spawns = spawns_per_frame(&wave(0., None), 1.);
+        assert_eq!(spawns[0], 1);
+        assert_eq!(spawns.iter().sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn wave_without_repeat_spawns_once() {
+        let spawns = spawns_per_frame(&wave(3., None), 10.);
+        assert_eq!(spawns.iter().sum::<usize>(), 1);
+    }
+
+    #[test]
+    fn repeated_wave_spawns_at_every_interval() {
+        let repeated = wave(0., Some(10.));
+        let spawns = spawns_per_frame(&repeated, 35.);
+        assert_eq!(spawns.iter().sum::<usize>(), 4);
+        assert_eq!(repeated.spawns_between(0., 35.), 4);
+        assert_eq!(repeated.spawns_between(10., 20.), 1);
+        assert_eq!(repeated.spawns_between(10.5, 19.5), 0);
+    }
+
+    #[test]
+    fn difficulty_curve_validation() {
+        let curve = |base, per_minute, max| DifficultyCurve { base, per_minute, max };
+        assert!(curve(1., 0.2, None).validate("health").is_ok());
+        assert!(curve(10., 5., Some(60.)).validate("group size").is_ok());
+        assert!(curve(f32::NAN, 0.2, None).validate("health").is_err());
+        assert!(curve(1., -0.2, None).validate("health").is_err());
+        assert!(curve(-1., 0.2, None).validate("health").is_err());
+        assert!(curve(1., 0.05, Some(0.5)).validate("speed").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_non_finite_patterns() {
+        let mut bad_radius = wave(0., None);
+        bad_radius.pattern = SpawnPattern::Cluster { radius: f32::NAN };
+        assert!(bad_radius.validate().is_err());
+
+        let mut bad_deadzone = wave(0., None);
+        bad_deadzone.pattern = SpawnPattern::Scattered { radius: 10., deadzone: f32::INFINITY };
+        assert!(bad_deadzone.validate().is_err());
+
+        assert!(wave(0., Some(10.)).validate().is_ok());
+    }
+}
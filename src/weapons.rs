@@ -0,0 +1,733 @@
+use std::f32::consts::PI;
+
+use bevy::math::Vec3Swizzles;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::HashSet;
+use heron::prelude::*;
+use rand::Rng;
+
+use crate::enemies::{Enemy, EnemyProjectile};
+use crate::spatial::EnemyGrid;
+use crate::status_effects::*;
+use crate::{is_enemy_layer, is_weapon_layer, GameLayer, Player, CAMERA_SCALE};
+
+pub const MAX_WEAPON_LEVEL: usize = 8;
+
+const CRIT_CHANCE: f64 = 0.1;
+const CRIT_MULTIPLIER: usize = 2;
+
+const AXE_HEAD_COLOR: Color = Color::rgb(0.52, 0.62, 0.8);
+const AXE_HEAD_SPEED: f32 = 2.; // radian/s
+const AXE_HEAD_RADIUS: f32 = 3.;
+const AXE_HEAD_RADIUS_STEP: f32 = 0.75;
+
+const MAGIC_BOLT_COLOR: Color = Color::rgb(0.62, 0.2, 0.85);
+const MAGIC_BOLT_SPEED: f32 = 15.;
+const MAGIC_BOLT_LIFETIME: f32 = 3.; // seconds
+const MAGIC_BOLT_SPREAD: f32 = 0.15; // radian between two bolts
+
+const AURA_COLOR: Color = Color::rgba(1., 0.9, 0.4, 0.25);
+const AURA_RADIUS: f32 = 2.;
+const AURA_RADIUS_STEP: f32 = 0.5;
+const CIRCLE_TEXTURE_SIZE: u32 = 128; // pixels
+
+const CHAIN_LIGHTNING_COLOR: Color = Color::rgb(0.6, 0.85, 1.);
+const CHAIN_LIGHTNING_RANGE: f32 = 8.; // distance of the first target from the player
+const CHAIN_LIGHTNING_JUMP_RADIUS: f32 = 4.;
+const CHAIN_LIGHTNING_ARC_WIDTH: f32 = 0.15;
+const CHAIN_LIGHTNING_ARC_LIFETIME: f32 = 0.2; // seconds
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeaponKind {
+    AxeHead,
+    MagicBolt,
+    Aura,
+    ChainLightning,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 4] =
+        [WeaponKind::AxeHead, WeaponKind::MagicBolt, WeaponKind::Aura, WeaponKind::ChainLightning];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WeaponKind::AxeHead => "Axe head",
+            WeaponKind::MagicBolt => "Magic bolt",
+            WeaponKind::Aura => "Aura",
+            WeaponKind::ChainLightning => "Chain lightning",
+        }
+    }
+}
+
+/// A weapon wielded by the player, the weapon entity is a child of the player
+/// and every hitbox it spawns refers to it with a `WeaponHitbox`.
+///
+/// Despawning the weapon entity recursively removes the weapon and its hitboxes.
+#[derive(Component)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub level: usize,
+    pub damage: usize,
+    /// The time between two attacks, not used by continuous weapons.
+    pub cooldown: Timer,
+    /// The size of the area covered by the weapon, its meaning depends on the kind.
+    pub area: f32,
+    /// The number of projectiles, or the number of jumps of the chain lightning.
+    pub projectiles: usize,
+    /// The speed of the projectiles, in world unit/s or radian/s.
+    pub speed: f32,
+    /// The number of enemies a projectile goes through before vanishing.
+    pub pierce: usize,
+    /// The speed at which a hit enemy is pushed away from the hitbox.
+    pub knockback: f32,
+    /// The status effect applied to the enemies hit by this weapon.
+    pub effect: Option<StatusEffect>,
+}
+
+impl Weapon {
+    pub fn new(kind: WeaponKind) -> Weapon {
+        match kind {
+            WeaponKind::AxeHead => Weapon {
+                kind,
+                level: 1,
+                damage: 10,
+                cooldown: Timer::from_seconds(0., false),
+                area: AXE_HEAD_RADIUS,
+                projectiles: 1,
+                speed: AXE_HEAD_SPEED,
+                pierce: 0,
+                knockback: 8.,
+                effect: Some(StatusEffect {
+                    kind: StatusEffectKind::Poison,
+                    duration: 3.,
+                    potency: 1.,
+                }),
+            },
+            WeaponKind::MagicBolt => Weapon {
+                kind,
+                level: 1,
+                damage: 10,
+                cooldown: Timer::from_seconds(1.2, true),
+                area: 0.3,
+                projectiles: 1,
+                speed: MAGIC_BOLT_SPEED,
+                pierce: 0,
+                knockback: 4.,
+                effect: Some(StatusEffect {
+                    kind: StatusEffectKind::Burn,
+                    duration: 2.,
+                    potency: 2.,
+                }),
+            },
+            WeaponKind::Aura => Weapon {
+                kind,
+                level: 1,
+                damage: 4,
+                cooldown: Timer::from_seconds(0.5, true),
+                area: AURA_RADIUS,
+                projectiles: 0,
+                speed: 0.,
+                pierce: 0,
+                knockback: 1.,
+                effect: Some(StatusEffect {
+                    kind: StatusEffectKind::Slow,
+                    duration: 1.,
+                    potency: 0.4,
+                }),
+            },
+            WeaponKind::ChainLightning => Weapon {
+                kind,
+                level: 1,
+                damage: 12,
+                cooldown: Timer::from_seconds(1.5, true),
+                area: CHAIN_LIGHTNING_JUMP_RADIUS,
+                projectiles: 2,
+                speed: 0.,
+                pierce: 0,
+                knockback: 2.,
+                effect: None,
+            },
+        }
+    }
+
+    /// Returns the damage dealt by a single hit of this weapon and whether it is a critical hit.
+    pub fn roll_damage<R: Rng>(&self, rng: &mut R) -> (usize, bool) {
+        if rng.gen_bool(CRIT_CHANCE) {
+            (self.damage * CRIT_MULTIPLIER, true)
+        } else {
+            (self.damage, false)
+        }
+    }
+
+    /// Returns the knockback applied to an enemy hit by a hitbox, directed along the hit normal.
+    pub fn knockback_between(&self, hitbox: Vec2, enemy: Vec2) -> Vec2 {
+        (enemy - hitbox).normalize_or_zero() * self.knockback
+    }
+
+    pub fn is_max_level(&self) -> bool {
+        self.level >= MAX_WEAPON_LEVEL
+    }
+
+    pub fn level_up(&mut self) {
+        if self.is_max_level() {
+            return;
+        }
+
+        self.level += 1;
+        match self.kind {
+            WeaponKind::AxeHead => match self.level {
+                2 | 4 | 6 | 8 => self.projectiles += 1,
+                3 | 7 => self.area += AXE_HEAD_RADIUS_STEP,
+                5 => self.speed *= 1.25,
+                _ => self.damage += 5,
+            },
+            WeaponKind::MagicBolt => match self.level {
+                2 | 6 => self.projectiles += 1,
+                3 | 7 => {
+                    let duration = self.cooldown.duration().mul_f32(0.85);
+                    self.cooldown.set_duration(duration);
+                }
+                4 => self.pierce += 1,
+                _ => self.damage += 5,
+            },
+            WeaponKind::Aura => match self.level {
+                2 | 4 | 6 => self.area += AURA_RADIUS_STEP,
+                // At its maximum level the aura freezes the enemies instead of slowing them.
+                8 => {
+                    self.effect = Some(StatusEffect {
+                        kind: StatusEffectKind::Freeze,
+                        duration: 0.6,
+                        potency: 0.,
+                    })
+                }
+                _ => self.damage += 2,
+            },
+            WeaponKind::ChainLightning => match self.level {
+                2 | 4 | 6 | 8 => self.projectiles += 1,
+                3 | 7 => self.area += 1.,
+                5 => {
+                    let duration = self.cooldown.duration().mul_f32(0.8);
+                    self.cooldown.set_duration(duration);
+                }
+                _ => self.damage += 5,
+            },
+        }
+    }
+}
+
+/// A projectile flying in a straight line until it hits too many enemies,
+/// its lifetime ends or it leaves the screen.
+#[derive(Component)]
+pub struct Projectile {
+    pub velocity: Vec2,
+    /// The number of enemies this projectile can still hit.
+    pub hits_left: usize,
+    pub lifetime: Timer,
+}
+
+/// Sent every time a weapon, or one of its status effects, deals damage to an enemy.
+pub struct WeaponHit {
+    pub enemy: Entity,
+    pub damage: usize,
+    pub crit: bool,
+    /// The velocity added to the enemy, before its knockback resistance is applied.
+    pub knockback: Vec2,
+    pub effect: Option<StatusEffect>,
+}
+
+/// Sent every time an enemy loses health.
+pub struct EnemyDamaged {
+    pub position: Vec2,
+    pub damage: usize,
+    pub crit: bool,
+}
+
+/// Links a hitbox to the weapon entity that spawned it.
+#[derive(Component)]
+pub struct WeaponHitbox(pub Entity);
+
+/// The textures generated at startup and used by the weapon sprites.
+pub struct WeaponTextures {
+    pub circle: Handle<Image>,
+}
+
+/// The enemies currently inside of an aura, they are hit at every cooldown of the weapon
+/// instead of when they enter the aura.
+#[derive(Default, Component)]
+pub struct AuraContacts(pub HashSet<Entity>);
+
+#[derive(Component)]
+pub struct AxeHead;
+
+/// A line drawn between two targets of a chain lightning, fading out until its timer finishes.
+#[derive(Component)]
+pub struct LightningArc(Timer);
+
+#[derive(Component)]
+pub struct RotationRadian(pub f32);
+
+pub fn setup_weapon_textures(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let circle = images.add(circle_image(CIRCLE_TEXTURE_SIZE));
+    commands.insert_resource(WeaponTextures { circle });
+}
+
+/// Generates a white disk on a transparent background, to be tinted by the sprite color.
+fn circle_image(size: u32) -> Image {
+    let radius = size as f32 / 2.;
+    let mut data = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let offset = Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - Vec2::splat(radius);
+            let alpha = if offset.length() <= radius { u8::MAX } else { 0 };
+            data.extend_from_slice(&[u8::MAX, u8::MAX, u8::MAX, alpha]);
+        }
+    }
+
+    Image::new(
+        Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+/// Spawns a new weapon of the given kind as a child of the player.
+pub fn add_weapon(
+    commands: &mut Commands,
+    textures: &WeaponTextures,
+    player: Entity,
+    kind: WeaponKind,
+) -> Entity {
+    let weapon = Weapon::new(kind);
+    let mut weapon_commands =
+        commands.spawn_bundle((Transform::identity(), GlobalTransform::identity()));
+    let weapon_entity = weapon_commands.id();
+
+    match kind {
+        WeaponKind::AxeHead => {
+            weapon_commands.with_children(|parent| {
+                spawn_axe_heads(parent, weapon_entity, weapon.projectiles, 0.)
+            });
+        }
+        WeaponKind::MagicBolt | WeaponKind::ChainLightning => (),
+        WeaponKind::Aura => {
+            let radius = weapon.area;
+            weapon_commands
+                .with_children(|parent| spawn_aura(parent, textures, weapon_entity, radius));
+        }
+    }
+
+    weapon_commands.insert(weapon);
+    commands.entity(player).push_children(&[weapon_entity]);
+    weapon_entity
+}
+
+/// Removes a weapon from the player along with its hitboxes,
+/// the projectiles it already fired no longer deal damage.
+pub fn remove_weapon(commands: &mut Commands, player: Entity, weapon: Entity) {
+    commands.entity(player).remove_children(&[weapon]);
+    commands.entity(weapon).despawn_recursive();
+}
+
+/// Spawns `count` axe heads evenly spaced on the orbit, the first one at the `phase` angle.
+fn spawn_axe_heads(parent: &mut ChildBuilder, weapon: Entity, count: usize, phase: f32) {
+    let step = 2. * PI / count as f32;
+    for i in 0..count {
+        let radian = (phase + step * i as f32).rem_euclid(2. * PI);
+        spawn_axe_head(parent, weapon, radian);
+    }
+}
+
+fn spawn_axe_head(parent: &mut ChildBuilder, weapon: Entity, radian: f32) {
+    parent
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: AXE_HEAD_COLOR,
+                custom_size: Some(Vec2::new(0.8, 0.8)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(RigidBody::Static)
+        .insert(CollisionShape::Sphere { radius: 0.6 })
+        .insert(CollisionLayers::none().with_group(GameLayer::Weapon).with_mask(GameLayer::Enemies))
+        .insert(RotationRadian(radian))
+        .insert(WeaponHitbox(weapon))
+        .insert(AxeHead);
+}
+
+fn spawn_aura(parent: &mut ChildBuilder, textures: &WeaponTextures, weapon: Entity, radius: f32) {
+    parent
+        .spawn_bundle(SpriteBundle {
+            // Render the aura under the player.
+            transform: Transform::from_translation(Vec3::new(0., 0., -1.)),
+            sprite: Sprite {
+                color: AURA_COLOR,
+                custom_size: Some(Vec2::splat(radius * 2.)),
+                ..Default::default()
+            },
+            texture: textures.circle.clone(),
+            ..Default::default()
+        })
+        .insert(RigidBody::Sensor)
+        .insert(CollisionShape::Sphere { radius })
+        .insert(CollisionLayers::none().with_group(GameLayer::Weapon).with_mask(GameLayer::Enemies))
+        .insert(AuraContacts::default())
+        .insert(WeaponHitbox(weapon));
+}
+
+pub fn tick_weapons_cooldown(time: Res<Time>, mut weapon_query: Query<&mut Weapon>) {
+    for mut weapon in weapon_query.iter_mut() {
+        weapon.cooldown.tick(time.delta());
+    }
+}
+
+/// Respawns the axe heads of a weapon when its number of projectiles changed,
+/// keeping them evenly spaced and in phase with the previous first axe head.
+pub fn sync_axe_heads(
+    mut commands: Commands,
+    weapon_query: Query<(Entity, &Weapon, &Children)>,
+    axe_head_query: Query<&RotationRadian, With<AxeHead>>,
+) {
+    for (entity, weapon, children) in weapon_query.iter() {
+        if weapon.kind != WeaponKind::AxeHead {
+            continue;
+        }
+
+        let axe_heads: Vec<_> = children
+            .iter()
+            .filter_map(|child| axe_head_query.get(*child).ok().map(|r| (*child, r.0)))
+            .collect();
+
+        if axe_heads.len() == weapon.projectiles {
+            continue;
+        }
+
+        let phase = axe_heads.first().map_or(0., |(_, radian)| *radian);
+        for (axe_head, _) in axe_heads {
+            commands.entity(axe_head).despawn_recursive();
+        }
+
+        let count = weapon.projectiles;
+        commands
+            .entity(entity)
+            .with_children(|parent| spawn_axe_heads(parent, entity, count, phase));
+    }
+}
+
+pub fn rotate_axe_heads(
+    time: Res<Time>,
+    weapon_query: Query<&Weapon>,
+    mut axe_head_query: Query<(&mut Transform, &mut RotationRadian, &WeaponHitbox), With<AxeHead>>,
+) {
+    for (mut transform, mut rotation, hitbox) in axe_head_query.iter_mut() {
+        let weapon = match weapon_query.get(hitbox.0) {
+            Ok(weapon) => weapon,
+            Err(_) => continue,
+        };
+
+        rotation.0 = (rotation.0 + weapon.speed * time.delta_seconds()).rem_euclid(2. * PI);
+
+        let x = rotation.0.cos() * weapon.area;
+        let y = rotation.0.sin() * weapon.area;
+        transform.translation = Vec3::new(x, y, 0.);
+    }
+}
+
+/// Hits the enemies that touch a hitbox, auras hit the enemies they contain on their own.
+///
+/// A projectile vanishes once it hit as many enemies as it can,
+/// even when it touches more of them during the same frame.
+#[allow(clippy::type_complexity)]
+pub fn weapon_hitboxes_hit_enemies(
+    mut commands: Commands,
+    mut events: EventReader<CollisionEvent>,
+    mut hits: EventWriter<WeaponHit>,
+    weapon_query: Query<&Weapon>,
+    mut hitbox_query: Query<
+        (&WeaponHitbox, &GlobalTransform, Option<&mut Projectile>),
+        Without<AuraContacts>,
+    >,
+    enemies_query: Query<&GlobalTransform, With<Enemy>>,
+) {
+    let mut rng = rand::thread_rng();
+    let contacts = events.iter().filter(|e| e.is_started()).filter_map(|event| {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        if is_weapon_layer(layers_1) && is_enemy_layer(layers_2) {
+            Some((entity_1, entity_2))
+        } else if is_weapon_layer(layers_2) && is_enemy_layer(layers_1) {
+            Some((entity_2, entity_1))
+        } else {
+            None
+        }
+    });
+
+    for (hitbox_entity, enemy_entity) in contacts {
+        let (hitbox, hitbox_transform, projectile) = match hitbox_query.get_mut(hitbox_entity) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        let (weapon, enemy_transform) =
+            match (weapon_query.get(hitbox.0), enemies_query.get(enemy_entity)) {
+                (Ok(weapon), Ok(enemy_transform)) => (weapon, enemy_transform),
+                _ => continue,
+            };
+
+        if let Some(mut projectile) = projectile {
+            if projectile.hits_left == 0 {
+                continue;
+            }
+            projectile.hits_left -= 1;
+            if projectile.hits_left == 0 {
+                commands.entity(hitbox_entity).despawn();
+            }
+        }
+
+        let (damage, crit) = weapon.roll_damage(&mut rng);
+        let knockback = weapon
+            .knockback_between(hitbox_transform.translation.xy(), enemy_transform.translation.xy());
+        hits.send(WeaponHit {
+            enemy: enemy_entity,
+            damage,
+            crit,
+            knockback,
+            effect: weapon.effect,
+        });
+    }
+}
+
+/// Fires magic bolts towards the closest enemy every time the weapon cooldown finishes.
+pub fn fire_magic_bolts(
+    mut commands: Commands,
+    grid: Res<EnemyGrid>,
+    weapon_query: Query<(Entity, &Weapon, &GlobalTransform)>,
+) {
+    for (entity, weapon, transform) in weapon_query.iter() {
+        if weapon.kind != WeaponKind::MagicBolt || !weapon.cooldown.just_finished() {
+            continue;
+        }
+
+        let origin = transform.translation.xy();
+        let target = match grid.nearest_k(origin, 1).first() {
+            Some((_, target)) => *target,
+            None => continue,
+        };
+
+        // Spread the bolts around the direction of the target.
+        let angle = (target - origin).y.atan2((target - origin).x);
+        let first = angle - MAGIC_BOLT_SPREAD * (weapon.projectiles - 1) as f32 / 2.;
+        for i in 0..weapon.projectiles {
+            let angle = first + MAGIC_BOLT_SPREAD * i as f32;
+            let direction = Vec2::new(angle.cos(), angle.sin());
+
+            commands
+                .spawn_bundle(SpriteBundle {
+                    transform: Transform::from_translation(origin.extend(95.)),
+                    sprite: Sprite {
+                        color: MAGIC_BOLT_COLOR,
+                        custom_size: Some(Vec2::splat(weapon.area * 2.)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(RigidBody::Sensor)
+                .insert(CollisionShape::Sphere { radius: weapon.area })
+                .insert(
+                    CollisionLayers::none()
+                        .with_groups(&[GameLayer::Weapon, GameLayer::Projectile])
+                        .with_mask(GameLayer::Enemies),
+                )
+                .insert(Projectile {
+                    velocity: direction * weapon.speed,
+                    hits_left: weapon.pierce + 1,
+                    lifetime: Timer::from_seconds(MAGIC_BOLT_LIFETIME, false),
+                })
+                .insert(WeaponHitbox(entity));
+        }
+    }
+}
+
+/// Moves the projectiles and despawns them once their lifetime ends, the projectiles
+/// of the player also vanish off-screen while the enemy ones can be fired from off-screen.
+#[allow(clippy::type_complexity)]
+pub fn move_projectiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    windows: Res<Windows>,
+    player_query: Query<&Transform, With<Player>>,
+    mut projectile_query: Query<
+        (Entity, &mut Transform, &mut Projectile, Option<&EnemyProjectile>),
+        Without<Player>,
+    >,
+) {
+    let player_transform = match player_query.iter().next() {
+        Some(transform) => transform,
+        None => return,
+    };
+
+    let half_screen = half_screen(&windows);
+
+    for (entity, mut transform, mut projectile, enemy_projectile) in projectile_query.iter_mut() {
+        transform.translation += (projectile.velocity * time.delta_seconds()).extend(0.);
+
+        let offset = (transform.translation.xy() - player_transform.translation.xy()).abs();
+        let off_screen =
+            enemy_projectile.is_none() && (offset.x > half_screen.x || offset.y > half_screen.y);
+        if projectile.lifetime.tick(time.delta()).finished() || off_screen {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Returns the half size of the visible area, the camera follows the player.
+fn half_screen(windows: &Windows) -> Vec2 {
+    windows
+        .get_primary()
+        .map(|w| Vec2::new(w.width(), w.height()) * CAMERA_SCALE / 2.)
+        .unwrap_or_else(|| Vec2::splat(f32::INFINITY))
+}
+
+pub fn track_aura_contacts(
+    mut events: EventReader<CollisionEvent>,
+    mut aura_query: Query<&mut AuraContacts>,
+) {
+    for event in events.iter() {
+        let (entity_1, entity_2) = event.rigid_body_entities();
+        let (layers_1, layers_2) = event.collision_layers();
+        let (aura_entity, enemy_entity) = if is_enemy_layer(layers_2) {
+            (entity_1, entity_2)
+        } else if is_enemy_layer(layers_1) {
+            (entity_2, entity_1)
+        } else {
+            continue;
+        };
+
+        if let Ok(mut contacts) = aura_query.get_mut(aura_entity) {
+            match event {
+                CollisionEvent::Started(..) => contacts.0.insert(enemy_entity),
+                CollisionEvent::Stopped(..) => contacts.0.remove(&enemy_entity),
+            };
+        }
+    }
+}
+
+/// Keeps the size of the auras in sync with the area of their weapon.
+pub fn sync_auras(
+    weapon_query: Query<&Weapon>,
+    mut aura_query: Query<(&WeaponHitbox, &mut CollisionShape, &mut Sprite), With<AuraContacts>>,
+) {
+    for (hitbox, mut shape, mut sprite) in aura_query.iter_mut() {
+        let radius = match weapon_query.get(hitbox.0) {
+            Ok(weapon) => weapon.area,
+            Err(_) => continue,
+        };
+
+        if !matches!(*shape, CollisionShape::Sphere { radius: r } if r == radius) {
+            *shape = CollisionShape::Sphere { radius };
+            sprite.custom_size = Some(Vec2::splat(radius * 2.));
+        }
+    }
+}
+
+/// Hits every enemy inside of an aura each time the cooldown of its weapon finishes.
+pub fn auras_hit_enemies(
+    mut hits: EventWriter<WeaponHit>,
+    weapon_query: Query<&Weapon>,
+    mut aura_query: Query<(&WeaponHitbox, &GlobalTransform, &mut AuraContacts)>,
+    enemies_query: Query<&GlobalTransform, With<Enemy>>,
+) {
+    let mut rng = rand::thread_rng();
+    for (hitbox, aura_transform, mut contacts) in aura_query.iter_mut() {
+        let weapon = match weapon_query.get(hitbox.0) {
+            Ok(weapon) if weapon.cooldown.just_finished() => weapon,
+            _ => continue,
+        };
+
+        // Enemies that died inside of the aura don't always emit a stopped event.
+        contacts.0.retain(|enemy| enemies_query.get(*enemy).is_ok());
+
+        for (enemy, enemy_transform) in
+            contacts.0.iter().filter_map(|e| enemies_query.get(*e).ok().map(|t| (*e, t)))
+        {
+            let (damage, crit) = weapon.roll_damage(&mut rng);
+            let knockback = weapon.knockback_between(
+                aura_transform.translation.xy(),
+                enemy_transform.translation.xy(),
+            );
+            hits.send(WeaponHit { enemy, damage, crit, knockback, effect: weapon.effect });
+        }
+    }
+}
+
+/// Strikes the closest enemy within `CHAIN_LIGHTNING_RANGE` of the player every time the cooldown
+/// of a chain lightning finishes, then jumps to the closest enemy not struck yet,
+/// as long as one is close enough.
+pub fn fire_chain_lightnings(
+    mut commands: Commands,
+    grid: Res<EnemyGrid>,
+    mut hits: EventWriter<WeaponHit>,
+    weapon_query: Query<(&Weapon, &GlobalTransform)>,
+) {
+    let mut rng = rand::thread_rng();
+    for (weapon, transform) in weapon_query.iter() {
+        if weapon.kind != WeaponKind::ChainLightning || !weapon.cooldown.just_finished() {
+            continue;
+        }
+
+        let mut from = transform.translation.xy();
+        let mut radius = CHAIN_LIGHTNING_RANGE;
+        let mut struck = Vec::with_capacity(weapon.projectiles + 1);
+        // The first strike isn't a jump.
+        for _ in 0..=weapon.projectiles {
+            let (enemy, position) = match grid.nearest(from, radius, |e| !struck.contains(&e)) {
+                Some(target) => target,
+                None => break,
+            };
+
+            let (damage, crit) = weapon.roll_damage(&mut rng);
+            let knockback = weapon.knockback_between(from, position);
+            hits.send(WeaponHit { enemy, damage, crit, knockback, effect: weapon.effect });
+            spawn_lightning_arc(&mut commands, from, position);
+
+            struck.push(enemy);
+            from = position;
+            radius = weapon.area;
+        }
+    }
+}
+
+fn spawn_lightning_arc(commands: &mut Commands, from: Vec2, to: Vec2) {
+    let offset = to - from;
+    commands
+        .spawn_bundle(SpriteBundle {
+            transform: Transform {
+                translation: ((from + to) / 2.).extend(96.),
+                rotation: Quat::from_rotation_z(offset.y.atan2(offset.x)),
+                ..Default::default()
+            },
+            sprite: Sprite {
+                color: CHAIN_LIGHTNING_COLOR,
+                custom_size: Some(Vec2::new(offset.length(), CHAIN_LIGHTNING_ARC_WIDTH)),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(LightningArc(Timer::from_seconds(CHAIN_LIGHTNING_ARC_LIFETIME, false)));
+}
+
+pub fn fade_lightning_arcs(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut arc_query: Query<(Entity, &mut Sprite, &mut LightningArc)>,
+) {
+    for (entity, mut sprite, mut arc) in arc_query.iter_mut() {
+        if arc.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        } else {
+            sprite.color.set_a(1. - arc.0.percent());
+        }
+    }
+}